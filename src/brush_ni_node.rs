@@ -159,6 +159,17 @@ impl BrushNiMatProps {
     }
 }
 
+/// Texture transform animation for a brush, emitted as an NiUVController.
+/// Scroll speeds are in texture repeats per second.
+#[derive(Debug, Default, PartialEq)]
+pub struct BrushNiUVProps {
+    pub scroll_u: Option<f32>,
+    pub scroll_v: Option<f32>,
+    pub tile_u: Option<f32>,
+    pub tile_v: Option<f32>,
+    pub period: Option<f32>,
+}
+
 pub struct BrushNiNode {
     pub vis_shape: NiTriShape,
     pub vis_data: NiTriShapeData,
@@ -171,6 +182,7 @@ pub struct BrushNiNode {
     pub distance_from_origin: SV3,
     // Mesh color values when doing more direct edits
     pub mat_props: BrushNiMatProps,
+    pub uv_props: BrushNiUVProps,
     // Textures and triangles are only used internally
     normals: Vec<SV3>,
     uv_sets: Vec<SV2>,
@@ -201,6 +213,10 @@ impl BrushNiNode {
     ) -> Vec<BrushNiNode> {
         let mut face_nodes = Vec::new();
 
+        if map_data.brush_is_origin(brush_id) {
+            return face_nodes;
+        }
+
         let faces_with_textures = Self::collect_faces_with_textures(&brush_id, map_data);
 
         for face_set in faces_with_textures {
@@ -235,6 +251,9 @@ impl BrushNiNode {
         let mut node = BrushNiNode::default();
 
         let entity_props = map_data.get_entity_properties(entity_id);
+        let is_illusionary = entity_props
+            .get(&"classname".to_string())
+            .is_some_and(|classname| *classname == "func_illusionary");
 
         ["Ambient", "Diffuse", "Emissive"]
             .iter()
@@ -311,6 +330,25 @@ impl BrushNiNode {
             );
         }
 
+        ["U", "V", "TileU", "TileV", "Period"]
+            .iter()
+            .for_each(|uv_prop| {
+                if let Some(prop) = entity_props.get(&format!("Material_UVScroll_{}", uv_prop)) {
+                    let value = Some(
+                        prop.parse::<f32>()
+                            .expect("Failed to parse float value from UV scroll properties!"),
+                    );
+                    match *uv_prop {
+                        "U" => node.uv_props.scroll_u = value,
+                        "V" => node.uv_props.scroll_v = value,
+                        "TileU" => node.uv_props.tile_u = value,
+                        "TileV" => node.uv_props.tile_v = value,
+                        "Period" => node.uv_props.period = value,
+                        _ => unreachable!(),
+                    }
+                }
+            });
+
         for face_id in faces.iter() {
             let texture_id = map_data.geomap.face_textures.get(face_id).unwrap();
             let texture_name = map_data.geomap.textures.get(texture_id).unwrap();
@@ -356,11 +394,7 @@ panic!("Critical error: Missing inverted face triangle indices for face_id: {:?}
             }
 
             // Test for water or slime types
-            if texture_name.to_ascii_lowercase().contains("slime")
-                || texture_name.to_ascii_lowercase().contains("water")
-                || texture_name.to_ascii_lowercase().contains("lava")
-                || texture_name.to_ascii_lowercase().contains("mwat")
-            {
+            if surfaces::is_liquid(&texture_name) {
                 surface_flags |= surfaces::NiBroomSurface::NoClip as u32;
                 println!("{face_id} interpreted as liquid")
             }
@@ -387,7 +421,7 @@ panic!("Critical error: Missing inverted face triangle indices for face_id: {:?}
 
             // There is minor edge case in this approach where if all faces of an object do not have collision then an empty collision root is created
             // This is exactly what we want, but, I worry it will have stupid consequences later
-            if surface_flags & surfaces::NiBroomSurface::NoClip as u32 == 0 {
+            if !is_illusionary && surface_flags & surfaces::NiBroomSurface::NoClip as u32 == 0 {
                 node.col_verts.extend(*vertices);
                 node.col_tris.push((*indices).to_vec());
             }
@@ -491,6 +525,7 @@ impl Default for BrushNiNode {
             col_tris: Vec::new(),
             distance_from_origin: SV3::default(),
             mat_props: BrushNiMatProps::default(),
+            uv_props: BrushNiUVProps::default(),
         }
     }
 }
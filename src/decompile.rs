@@ -0,0 +1,144 @@
+use std::{fmt::Write, fs};
+
+use clap::ArgMatches;
+use tes3::esp::{Cell, CellFlags, Plugin, Reference};
+
+/// Half-width of the origin brush which anchors worldspawn in decompiled maps
+const ANCHOR_SIZE: i32 = 8;
+
+pub fn decompile(args: &ArgMatches) {
+    let plugin_name = args.get_one::<String>("PLUGIN_PATH").unwrap();
+    let cell_name = args.get_one::<String>("CELL_NAME").unwrap();
+    let map_name = args.get_one::<String>("MAP_NAME").unwrap();
+    let scale_mode = args.get_one::<f32>("SCALE").unwrap_or(&1.0);
+
+    let plugin = Plugin::from_path(plugin_name).expect(&format!("Reading {plugin_name} failed!"));
+
+    let cell = plugin
+        .objects_of_type::<Cell>()
+        .find(|cell| {
+            cell.data.flags.contains(CellFlags::IS_INTERIOR)
+                && cell.name.eq_ignore_ascii_case(cell_name)
+        })
+        .expect(&format!(
+            "No interior cell named {cell_name} was found in {plugin_name}!"
+        ));
+
+    if cell.name.contains('\'') {
+        println!("Warning! {} contains an apostrophe, which the map parser cannot read back. Rename it in worldspawn before compiling.", cell.name);
+    }
+
+    let mut map = String::from("// Game: Morrowind\n// Format: Quake2 (Valve)\n");
+
+    write_worldspawn(&mut map, cell);
+
+    // Sort by reference number so that repeated decompiles produce the same file
+    let mut references: Vec<&Reference> = cell
+        .references
+        .values()
+        .filter(|reference| reference.deleted.is_none())
+        .collect();
+    references.sort_by_key(|reference| (reference.mast_index, reference.refr_index));
+
+    for (index, reference) in references.iter().enumerate() {
+        write_reference(&mut map, index + 1, reference, scale_mode);
+    }
+
+    fs::write(map_name, map).expect(&format!("Saving {map_name} failed!"));
+
+    println!(
+        "Wrote {} references from {} to {map_name} successfully.",
+        references.len(),
+        cell.name
+    );
+}
+
+fn write_worldspawn(map: &mut String, cell: &Cell) {
+    let flags = cell.data.flags;
+
+    let _ = writeln!(map, "// entity 0\n{{");
+    write_property(map, "classname", "worldspawn");
+    write_property(map, "Name", &cell.name);
+
+    for (prop, flag) in [
+        ("FakeExterior", CellFlags::BEHAVES_LIKE_EXTERIOR),
+        ("RestIsIllegal", CellFlags::RESTING_IS_ILLEGAL),
+        ("HasWater", CellFlags::HAS_WATER),
+    ] {
+        if flags.contains(flag) {
+            write_property(map, prop, "1");
+        }
+    }
+
+    if let Some(region) = &cell.region {
+        write_property(map, "Region", region);
+    }
+
+    if let Some(water_height) = cell.water_height {
+        write_property(map, "WaterHeight", &water_height.to_string());
+    }
+
+    if let Some(atmosphere) = &cell.atmosphere_data {
+        write_property(map, "FogDensity", &atmosphere.fog_density.to_string());
+        write_property(map, "Fog_color", &color_string(&atmosphere.fog_color));
+        write_property(
+            map,
+            "Ambient_color",
+            &color_string(&atmosphere.ambient_color),
+        );
+        write_property(map, "Sun_color", &color_string(&atmosphere.sunlight_color));
+    }
+
+    // Worldspawn needs at least one brush to compile, and an origin brush
+    // keeps the scene mesh anchored to the cell origin without rendering anything
+    let _ = writeln!(map, "// brush 0\n{{");
+    let (min, max) = (-ANCHOR_SIZE, ANCHOR_SIZE);
+    for face in [
+        format!("( {min} {min} {min} ) ( {min} {} {min} ) ( {min} {min} {} ) origin [ 0 -1 0 0 ] [ 0 0 -1 0 ] 0 1 1", min + 1, min + 1),
+        format!("( {min} {min} {min} ) ( {min} {min} {} ) ( {} {min} {min} ) origin [ 1 0 0 0 ] [ 0 0 -1 0 ] 0 1 1", min + 1, min + 1),
+        format!("( {min} {min} {min} ) ( {} {min} {min} ) ( {min} {} {min} ) origin [ -1 0 0 0 ] [ 0 -1 0 0 ] 0 1 1", min + 1, min + 1),
+        format!("( {max} {max} {max} ) ( {max} {} {max} ) ( {} {max} {max} ) origin [ 1 0 0 0 ] [ 0 -1 0 0 ] 0 1 1", max + 1, max + 1),
+        format!("( {max} {max} {max} ) ( {} {max} {max} ) ( {max} {max} {} ) origin [ -1 0 0 0 ] [ 0 0 -1 0 ] 0 1 1", max + 1, max + 1),
+        format!("( {max} {max} {max} ) ( {max} {max} {} ) ( {max} {} {max} ) origin [ 0 1 0 0 ] [ 0 0 -1 0 ] 0 1 1", max + 1, max + 1),
+    ] {
+        let _ = writeln!(map, "{face}");
+    }
+    let _ = writeln!(map, "}}\n}}");
+}
+
+fn write_reference(map: &mut String, entity_index: usize, reference: &Reference, scale_mode: &f32) {
+    let [x, y, z] = reference.translation.map(|axis| axis / scale_mode);
+
+    let _ = writeln!(map, "// entity {entity_index}\n{{");
+    write_property(map, "classname", "world_Reference");
+    write_property(map, "RefId", &reference.id);
+    write_property(map, "origin", &format!("{x} {y} {z}"));
+    write_property(map, "mangle", &mangle_string(&reference.rotation));
+
+    if let Some(scale) = reference.scale {
+        if scale != 1.0 {
+            write_property(map, "Scale", &scale.to_string());
+        }
+    }
+
+    let _ = writeln!(map, "}}");
+}
+
+/// Inverse of the rotation handling in main, which swizzles and negates the mangle
+fn mangle_string(rotation: &[f32; 3]) -> String {
+    format!(
+        "{} {} {}",
+        -rotation[1].to_degrees(),
+        -rotation[2].to_degrees(),
+        -rotation[0].to_degrees()
+    )
+}
+
+fn color_string(color: &[u8; 4]) -> String {
+    format!("{} {} {}", color[0], color[1], color[2])
+}
+
+fn write_property(map: &mut String, key: &str, value: &str) {
+    // Map values are quoted, so they can't contain quotes of their own
+    let _ = writeln!(map, "\"{key}\" \"{}\"", value.replace('"', ""));
+}
@@ -0,0 +1,139 @@
+use shambler::Vector3 as SV3;
+use std::cmp::min;
+use tes3::esp::{Cell, Reference, StartScript, TES3Object};
+
+use crate::{game_object, get_prop, master_cell::MasterCell, MapData};
+
+/// How far below the cell sunk references are moved, in game units
+const SINK_DEPTH: f32 = 20000.0;
+
+/// Removes the master cell's references inside every `world_DeleteVolume`.
+/// Deleted references can break scripts and quests which use them, so volumes
+/// can instead move them out of sight underneath the cell, where a start script
+/// disables them. Returns one line for each reference which was removed.
+pub fn apply(
+    map_data: &MapData,
+    master_cell: Option<&MasterCell>,
+    master_index: u32,
+    cell: &mut Cell,
+    scale_mode: &f32,
+    map_dir: &str,
+    created_objects: &mut Vec<TES3Object>,
+) -> Vec<String> {
+    let volumes: Vec<_> = map_data
+        .brush_entities()
+        .into_iter()
+        .filter(|(entity_id, _)| {
+            get_prop("classname", &map_data.get_entity_properties(entity_id))
+                == "world_DeleteVolume"
+        })
+        .collect();
+
+    if volumes.is_empty() {
+        return Vec::new();
+    }
+
+    let Some(master_cell) = master_cell else {
+        println!("Warning! world_DeleteVolume only removes references from a master cell, but worldspawn has no Master. Ignoring {} volumes.", volumes.len());
+        return Vec::new();
+    };
+
+    let mut report = Vec::new();
+    let mut sunk_ids: Vec<String> = Vec::new();
+
+    for reference in &master_cell.references {
+        // References the master takes from its own masters are numbered against its header, not ours
+        if reference.mast_index != 0 {
+            continue;
+        }
+
+        // Volumes are in map units, before the anchor moved the map into the master's coordinates
+        let position = (SV3::from(reference.translation) - master_cell.offset) / *scale_mode;
+
+        let Some((entity_id, _)) = volumes.iter().find(|(_, brushes)| {
+            brushes.iter().any(|brush_id| {
+                !map_data.brush_is_origin(brush_id) && map_data.brush_contains(brush_id, position)
+            })
+        }) else {
+            continue;
+        };
+
+        let mut removed = Reference {
+            mast_index: master_index,
+            ..reference.clone()
+        };
+
+        let action = match get_prop("Mode", &map_data.get_entity_properties(entity_id)).as_str() {
+            "1" => {
+                removed.translation[2] -= SINK_DEPTH;
+                if !sunk_ids.contains(&reference.id) {
+                    sunk_ids.push(reference.id.clone());
+                }
+                "Sunk"
+            }
+            _ => {
+                removed.deleted = Some(0);
+                "Deleted"
+            }
+        };
+
+        report.push(format!(
+            "{action} {} (reference {}) at {:?}",
+            reference.id, reference.refr_index, reference.translation
+        ));
+        cell.references
+            .insert((master_index, reference.refr_index), removed);
+    }
+
+    if !sunk_ids.is_empty() {
+        created_objects.extend(disable_script(master_cell, &sunk_ids, map_dir));
+    }
+
+    report
+}
+
+/// Plugins can't disable a reference by themselves, so a start script does it once the
+/// player is in the cell. Scripts find references by id, so when the master places an
+/// object more than once, only one of them is disabled and the rest are only moved.
+fn disable_script(master_cell: &MasterCell, sunk_ids: &[String], map_dir: &str) -> Vec<TES3Object> {
+    let script_id = format!("{map_dir}-DV");
+    let script_id = script_id[..min(script_id.len(), 32)].to_string();
+    let cell_name = &master_cell.cell.name;
+
+    let mut text = format!(
+        "Begin {script_id}\r\n\
+         \r\n\
+         if ( GetPCCell \"{cell_name}\" == 0 )\r\n\
+         \treturn\r\n\
+         endif\r\n\
+         \r\n"
+    );
+
+    for ref_id in sunk_ids {
+        let placements = master_cell
+            .references
+            .iter()
+            .filter(|reference| reference.id.eq_ignore_ascii_case(ref_id))
+            .count();
+
+        if placements > 1 {
+            println!("Warning! {cell_name} places {ref_id} {placements} times, so a script can't tell which to disable. Sunk copies of it are only moved.");
+            continue;
+        }
+
+        text.push_str(&format!("\"{ref_id}\"->Disable\r\n"));
+    }
+
+    text.push_str(&format!(
+        "\r\nStopScript {script_id}\r\n\r\nEnd {script_id}\r\n"
+    ));
+
+    vec![
+        game_object::script(&script_id, &text),
+        TES3Object::StartScript(StartScript {
+            id: script_id.clone(),
+            script: script_id,
+            ..Default::default()
+        }),
+    ]
+}
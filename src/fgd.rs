@@ -0,0 +1,1134 @@
+//! Every entity class and property the compiler reads. `morrobroom fgd` renders
+//! this into the Morrowind.fgd trenchbroom loads, so new properties belong here
+//! alongside the code that consumes them.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+
+#[rustfmt::skip]
+pub const SKILLS: &[&str] = &[
+    "Block", "Armorer", "MediumArmor", "HeavyArmor", "BluntWeapon", "LongBlade", "Axe", "Spear",
+    "Athletics", "Enchant", "Destruction", "Alteration", "Illusion", "Conjuration", "Mysticism",
+    "Restoration", "Alchemy", "Unarmored", "Security", "Sneak", "Acrobatics", "LightArmor",
+    "ShortBlade", "Marksman", "Mercantile", "Speechcraft", "HandToHand",
+];
+
+#[rustfmt::skip]
+pub const ATTRIBUTES: &[&str] = &[
+    "Strength", "Intelligence", "Willpower", "Agility", "Speed", "Endurance", "Personality", "Luck",
+];
+
+#[rustfmt::skip]
+pub const MAGIC_EFFECTS: &[&str] = &[
+    "Water Breathing", "Swift Swim", "Water Walking", "Shield", "Fire Shield", "Lightning Shield",
+    "Frost Shield", "Burden", "Feather", "Jump", "Levitate", "SlowFall", "Lock", "Open",
+    "Fire Damage", "Shock Damage", "Frost Damage", "Drain Attribute", "Drain Health",
+    "Drain Magicka", "Drain Fatigue", "Drain Skill", "Damage Attribute", "Damage Health",
+    "Damage Magicka", "Damage Fatigue", "Damage Skill", "Poison", "Weakness to Fire",
+    "Weakness to Frost", "Weakness to Shock", "Weakness to Magicka", "Weakness to Common Disease",
+    "Weakness to Blight Disease", "Weakness to Corprus Disease", "Weakness to Poison",
+    "Weakness to Normal Weapons", "Disintegrate Weapon", "Disintegrate Armor", "Invisibility",
+    "Chameleon", "Light", "Sanctuary", "Night Eye", "Charm", "Paralyze", "Silence", "Blind",
+    "Sound", "Calm Humanoid", "Calm Creature", "Frenzy Humanoid", "Frenzy Creature",
+    "Demoralize Humanoid", "Demoralize Creature", "Rally Humanoid", "Rally Creature", "Dispel",
+    "Soultrap", "Telekinesis", "Mark", "Recall", "Divine Intervention", "Almsivi Intervention",
+    "Detect Animal", "Detect Enchantment", "Detect Key", "Spell Absorption", "Reflect",
+    "Cure Common Disease", "Cure Blight Disease", "Cure Corprus Disease", "Cure Poison",
+    "Cure Paralyzation", "Restore Attribute", "Restore Health", "Restore Magicka",
+    "Restore Fatigue", "Restore Skill", "Fortify Attribute", "Fortify Health", "Fortify Magicka",
+    "Fortify Fatigue", "Fortify Skill", "Fortify Maximum Magicka", "Absorb Attribute",
+    "Absorb Health", "Absorb Magicka", "Absorb Fatigue", "Absorb Skill", "Resist Fire",
+    "Resist Frost", "Resist Shock", "Resist Magicka", "Resist Common Disease",
+    "Resist Blight Disease", "Resist Corprus Disease", "Resist Poison", "Resist Normal Weapons",
+    "Resist Paralysis", "Remove Curse", "Turn Undead", "Summon Scamp", "Summon Clannfear",
+    "Summon Daedroth", "Summon Dremora", "Summon Ancestral Ghost", "Summon Skeletal Minion",
+    "Summon Bonewalker", "Summon Greater Bonewalker", "Summon Bonelord", "Summon Winged Twilight",
+    "Summon Hunger", "Summon Golden Saint", "Summon Flame Atronach", "Summon Frost Atronach",
+    "Summon Storm Atronach", "Fortify Attack", "Command Creature", "Command Humanoid",
+    "Bound Dagger", "Bound Longsword", "Bound Mace", "Bound Battle Axe", "Bound Spear",
+    "Bound Longbow", "EXTRA SPELL", "Bound Cuirass", "Bound Helm", "Bound Boots", "Bound Shield",
+    "Bound Gloves", "Corprus", "Vampirism", "Summon Centurion Sphere", "Sun Damage",
+    "Stunted Magicka", "Summon FabricantTR", "Call WolfBM", "Call BearBM", "Summon BonewolfBM",
+];
+
+#[rustfmt::skip]
+pub const BIPED_SLOTS: &[&str] = &[
+    "Head", "Hair", "Neck", "Chest", "Groin", "Skirt", "Right Hand", "Left Hand", "Right Wrist",
+    "Left Wrist", "Shield", "Right Forearm", "Left Forearm", "Right Upper Arm", "Left Upper Arm",
+    "Right Foot", "Left Foot", "Right Ankle", "Left Ankle", "Right Knee", "Left Knee",
+    "Right Upper Leg", "Left Upper Leg", "Right Pauldron", "Left Pauldron", "Weapon", "Tail",
+];
+
+/// Keys which trenchbroom, the map format or morrobroom itself manage, and are valid on any entity
+const ENGINE_PROPERTIES: &[&str] = &["classname", "origin", "mangle", "_mb_uid"];
+
+/// Keys trenchbroom and other quake editors write to worldspawn for their own use
+const WORLDSPAWN_EDITOR_PROPERTIES: &[&str] =
+    &["mapversion", "wad", "_wad", "_generator", "message"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClassType {
+    Base,
+    Solid,
+    Point,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PropertyType {
+    String,
+    Integer,
+    Float,
+    Color,
+    Choices(Vec<(i32, String)>),
+    Flags(Vec<(u32, String)>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Property {
+    pub name: String,
+    pub kind: PropertyType,
+    pub description: String,
+    pub default: Option<String>,
+}
+
+impl Property {
+    fn new(name: &str, kind: PropertyType, description: &str) -> Property {
+        Property {
+            name: name.to_string(),
+            kind,
+            description: description.to_string(),
+            default: None,
+        }
+    }
+
+    pub fn string(name: &str, description: &str) -> Property {
+        Property::new(name, PropertyType::String, description)
+    }
+
+    pub fn integer(name: &str, description: &str) -> Property {
+        Property::new(name, PropertyType::Integer, description)
+    }
+
+    pub fn float(name: &str, description: &str) -> Property {
+        Property::new(name, PropertyType::Float, description)
+    }
+
+    pub fn color(name: &str, description: &str) -> Property {
+        Property::new(name, PropertyType::Color, description)
+    }
+
+    pub fn choices(name: &str, description: &str, choices: &[(i32, &str)]) -> Property {
+        Property::new(
+            name,
+            PropertyType::Choices(
+                choices
+                    .iter()
+                    .map(|(value, label)| (*value, label.to_string()))
+                    .collect(),
+            ),
+            description,
+        )
+    }
+
+    /// Choices whose values are the index of each label
+    pub fn indexed(name: &str, description: &str, labels: &[&str]) -> Property {
+        Property::new(
+            name,
+            PropertyType::Choices(
+                labels
+                    .iter()
+                    .enumerate()
+                    .map(|(value, label)| (value as i32, label.to_string()))
+                    .collect(),
+            ),
+            description,
+        )
+    }
+
+    pub fn boolean(name: &str, description: &str) -> Property {
+        Property::choices(name, description, &[(0, "False"), (1, "True")]).default("0")
+    }
+
+    pub fn flags(name: &str, description: &str, flags: &[(u32, &str)]) -> Property {
+        Property::new(
+            name,
+            PropertyType::Flags(
+                flags
+                    .iter()
+                    .map(|(value, label)| (*value, label.to_string()))
+                    .collect(),
+            ),
+            description,
+        )
+    }
+
+    pub fn default(mut self, default: &str) -> Property {
+        self.default = Some(default.to_string());
+        self
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EntityClass {
+    pub class_type: ClassType,
+    pub name: String,
+    pub description: String,
+    pub bases: Vec<String>,
+    pub attributes: Vec<String>,
+    pub properties: Vec<Property>,
+}
+
+impl EntityClass {
+    fn new(class_type: ClassType, name: &str, description: &str) -> EntityClass {
+        EntityClass {
+            class_type,
+            name: name.to_string(),
+            description: description.to_string(),
+            bases: Vec::new(),
+            attributes: Vec::new(),
+            properties: Vec::new(),
+        }
+    }
+
+    pub fn base(name: &str, description: &str) -> EntityClass {
+        EntityClass::new(ClassType::Base, name, description)
+    }
+
+    pub fn solid(name: &str, description: &str) -> EntityClass {
+        EntityClass::new(ClassType::Solid, name, description)
+    }
+
+    pub fn point(name: &str, description: &str) -> EntityClass {
+        EntityClass::new(ClassType::Point, name, description)
+    }
+
+    pub fn bases(mut self, bases: &[&str]) -> EntityClass {
+        self.bases = bases.iter().map(|base| base.to_string()).collect();
+        self
+    }
+
+    /// Editor-only decorations such as size(), iconsprite() or model()
+    pub fn attribute(mut self, attribute: &str) -> EntityClass {
+        self.attributes.push(attribute.to_string());
+        self
+    }
+
+    pub fn properties(mut self, properties: Vec<Property>) -> EntityClass {
+        self.properties.extend(properties);
+        self
+    }
+}
+
+pub fn classes() -> Vec<EntityClass> {
+    let mut classes = vec![
+        EntityClass::base("material", "Brush material").properties(material_properties()),
+        EntityClass::solid("world_Detail", "Static detail brushes")
+            .bases(&["material"])
+            .properties(vec![
+                Property::string("RefId", "Ref Id for the object").default("agronian guy"),
+                Property::string("Name", "Ingame readable name for the object").default("Tarhiel"),
+                origin_property(),
+            ]),
+        EntityClass::solid("func_detail", "Detail brushes, compiled into the worldspawn mesh").bases(&["material"]),
+        EntityClass::solid("func_wall", "Brushes compiled into their own static").bases(&["material"]),
+        EntityClass::solid("func_illusionary", "Brushes compiled into their own static, which can be walked through")
+            .bases(&["material"]),
+        EntityClass::solid("func_scatter", "Places objects at random across the top of this brush, or the worldspawn floors inside it")
+            .properties(
+                [
+                    Property::boolean("OnWorld", "Scatter across the worldspawn floors inside this brush instead of its own top faces"),
+                    Property::integer("Seed", "Changes the layout. The same seed always places the same objects.").default("0"),
+                    Property::float("Density", "Objects per 128x128 unit square of surface").default("1.0"),
+                    Property::float("MinScale", "Smallest scale of a placed object, from 0.5 to 2").default("1.0"),
+                    Property::float("MaxScale", "Largest scale of a placed object, from 0.5 to 2").default("1.0"),
+                    Property::boolean("RandomYaw", "Turn each object to a random direction").default("1"),
+                ]
+                .into_iter()
+                .chain(scatter_properties(8))
+                .collect(),
+            ),
+        EntityClass::solid("world_DeleteVolume", "Removes the worldspawn Master cell's references inside this brush")
+            .properties(vec![Property::choices(
+                "Mode",
+                "How references are removed. Sinking keeps them for scripts and quests which still use them.",
+                &[(0, "Delete"), (1, "Sink below the cell and disable")],
+            )
+            .default("0")]),
+        EntityClass::solid("worldspawn", "World entity")
+            .bases(&["material"])
+            .properties(vec![
+                Property::boolean("FakeExterior", "Use sky for this cell"),
+                Property::boolean("HasWater", "Use water for this cell"),
+                Property::boolean("RestIsIllegal", "Whether resting in this cell is legal or not."),
+                Property::string("Region", "Region to use for interior weather")
+                    .default("Ascadian Isles Region"),
+                Property::string("Name", "Override the cell name for this map. By default the name of the map is used for the interior."),
+                Property::string("Master", "Master file, such as Morrowind.esm, whose interior cell named by Name this map adds to instead of creating a new cell"),
+                Property::float("WaterHeight", "Water Height for this cell. Ignored if not flagged to use water.")
+                    .default("0"),
+                Property::color("Ambient_color", "Ambient lighting color").default("15 15 15"),
+                Property::float("FogDensity", "Density of interior fog. Set between 0 and 1.")
+                    .default("0"),
+                Property::color("Fog_color", "Color used for interior fog").default("15 15 15"),
+                Property::color("Sun_color", "Color used for interior sunlight").default("15 15 15"),
+                Property::choices(
+                    "PathGrid",
+                    "Path grid generation",
+                    &[(0, "Generated and info_pathnode"), (1, "info_pathnode only"), (2, "None")],
+                )
+                .default("0"),
+                Property::float("PathGridSpacing", "Distance between generated path grid nodes").default("128"),
+            ]),
+        EntityClass::base("PlayerClass", "Player sized entity")
+            .attribute("size(-16 -16 -34, 16 16 33)")
+            .attribute("color(0 255 0)"),
+        EntityClass::point("info_player_start", "Player Scale Reference").bases(&["PlayerClass"]),
+        EntityClass::point("misc_prefab", "Places every object from another map here, as one unit")
+            .attribute("size(-16 -16 -16, 16 16 16)")
+            .attribute("color(0 128 255)")
+            .properties(vec![
+                Property::string("Prefab", "Map file to place, relative to this map"),
+                Property::float("Scale", "Scale of the prefab").default("1.0"),
+            ]),
+        EntityClass::point("info_master_anchor", "Marks where a reference from the worldspawn Master cell sits in the map, so the map lines up with it")
+            .attribute("size(-8 -8 -8, 8 8 8)")
+            .attribute("color(255 0 255)")
+            .properties(vec![
+                Property::string("RefId", "Ref Id of the reference in the master cell"),
+                Property::integer("Occurrence", "Which reference to use when the cell has several with this Ref Id, counting from 1 in reference order").default("1"),
+            ]),
+        EntityClass::point("info_pathnode", "Path grid node, placed on the floor")
+            .attribute("size(-8 -8 0, 8 8 16)")
+            .attribute("color(255 128 0)")
+            .properties(vec![
+                Property::string("targetname", "Name other path nodes use to link to this one"),
+                Property::string("target", "Names of path nodes to link to, separated by spaces"),
+            ]),
+        EntityClass::base("Ownership", "Reference Ownership").properties(vec![
+            Property::string("Owner", "NPC which owns this placement. Optional."),
+            Property::string("OwnerFaction", "Faction which owns this placement. Optional."),
+            Property::integer("FactionRank", "Faction rank needed to use this placement freely").default("0"),
+            Property::string("GlobalVariable", "Global which allows use of this placement when set. Optional."),
+        ]),
+        EntityClass::base("Lockable", "Reference Lock").properties(vec![
+            Property::integer("LockLevel", "Lock level of this placement. Optional. 0 is lockable but unlocked."),
+            Property::string("Key", "Item id which unlocks this placement. Optional."),
+            Property::string("Trap", "Spell id cast on whoever opens this placement. Optional."),
+        ]),
+        EntityClass::base("baseObject", "Base Object")
+            .bases(&["material", "Ownership"])
+            .properties(vec![
+                Property::string("RefId", "Ref Id for the object").default("agronian guy"),
+                Property::string("Name", "Ingame readable name for the object").default("Tarhiel"),
+                Property::string("Model", "Model used by the gameobject. Optional. Uses the current brush if empty."),
+                Property::string("ScriptFile", "MWScript source file, relative to the map. Optional. {{ScriptId}} and {{<Property>}} placeholders are filled in from this entity, and the script replaces any set in Script."),
+                origin_property(),
+            ]),
+        EntityClass::base("Referenceable", "Base Entity")
+            .bases(&["baseObject"])
+            .properties(vec![
+                script_property(),
+                Property::string("Icon", "Ingame icon used by the object. Visible within TrenchBroom. Be sure to include the relative path to the icon.")
+                    .default("tx_goldicon.tga"),
+                Property::integer("Value", "Gold value of the object ingame").default("420"),
+                Property::float("Weight", "Weight of the object in Todd units.").default("0.0"),
+            ]),
+        EntityClass::base("Wearable", "Wearable")
+            .bases(&["Referenceable"])
+            .properties(vec![
+                Property::string("Enchantment", "Enchantment attached to the item. Can be defined in the same map with def_Enchantment.")
+                    .default("sc_icarianflight_en"),
+                Property::integer("EnchantmentPoints", "Strength of possible enchantments applied to this particular item.")
+                    .default("60"),
+            ]),
+        EntityClass::base("BodyParts", "Body Part Set").properties(biped_properties(8)),
+        EntityClass::base("MagicEffect1", "(Enchantment) Magic Effect")
+            .properties(effect_properties(8)),
+        EntityClass::base("MagicEffect2", "(Ingredient) Magic Effect")
+            .properties(effect_properties(4)),
+        EntityClass::base("ArmorData", "Armor Data").properties(vec![
+            Property::indexed(
+                "ArmorType",
+                "Armor Type",
+                &[
+                    "Helmet", "Cuirass", "Left Pauldron", "Right Pauldron", "Greaves", "Boots",
+                    "Left Gauntlet", "Right Gauntlet", "Shield", "Left Bracer", "Right Bracer",
+                ],
+            )
+            .default("0"),
+            Property::integer("Health", "Total armor durability").default("0"),
+            Property::integer("ArmorRating", "Total defensive capability of this armor").default("0"),
+        ]),
+        EntityClass::solid("item_Armor", "Base Armor Entity")
+            .bases(&["Wearable", "ArmorData", "BodyParts"]),
+        EntityClass::solid("item_Clothing", "Base Clothing Entity")
+            .bases(&["Wearable", "BodyParts"])
+            .properties(vec![Property::indexed(
+                "ClothingType",
+                "Clothing Type",
+                &[
+                    "Pants", "Shoes", "Shirt", "Belt", "Robe", "Right Glove", "Left Glove", "Skirt",
+                    "Ring", "Amulet",
+                ],
+            )
+            .default("0")]),
+        EntityClass::solid("item_Weapon", "Base Weapon Entity")
+            .bases(&["Wearable"])
+            .properties(vec![
+                Property::indexed(
+                    "WeaponType",
+                    "Weapon Type",
+                    &[
+                        "Short Blade One Hand", "Long Blade One Hand", "Long Blade Two Hand",
+                        "Blunt One Hand", "Blunt Two Close", "Blunt Two Wide", "Spear Two Wide",
+                        "Axe One Hand", "Axe Two Hand", "Marksman Bow", "Marksman Crossbow",
+                        "Marksman Thrown", "Arrow", "Bolt",
+                    ],
+                )
+                .default("0"),
+                Property::integer("Health", "Total weapon durability").default("0"),
+                Property::float("Speed", "Attack speed multiplier").default("1.0"),
+                Property::float("Reach", "Attack reach multiplier").default("1.0"),
+                Property::integer("ChopMin", "Minimum chop damage").default("0"),
+                Property::integer("ChopMax", "Maximum chop damage").default("0"),
+                Property::integer("SlashMin", "Minimum slash damage").default("0"),
+                Property::integer("SlashMax", "Maximum slash damage").default("0"),
+                Property::integer("ThrustMin", "Minimum thrust damage").default("0"),
+                Property::integer("ThrustMax", "Maximum thrust damage").default("0"),
+                Property::flags(
+                    "WeaponFlags",
+                    "Weapon flags",
+                    &[(1, "Ignores Normal Weapon Resistance"), (2, "Silver")],
+                ),
+            ]),
+        EntityClass::base("ToolData", "Tool Data").properties(vec![
+            Property::float("Quality", "Quality of the tool. Multiplicative, so suggested to stay between 1 and 0.")
+                .default("1.0"),
+            Property::integer("Uses", "Number of uses before the tool breaks").default("25"),
+        ]),
+        EntityClass::solid("item_Lockpick", "Base Lockpick Entity")
+            .bases(&["Referenceable", "ToolData"]),
+        EntityClass::solid("item_Probe", "Base Probe Entity")
+            .bases(&["Referenceable", "ToolData"]),
+        EntityClass::solid("item_RepairItem", "Base Repair Item Entity")
+            .bases(&["Referenceable", "ToolData"]),
+        EntityClass::solid("world_Activator", "Base Activator Entity")
+            .bases(&["baseObject"])
+            .properties(vec![script_property()]),
+        EntityClass::base("Mover", "Keyframed brush entity")
+            .bases(&["baseObject"])
+            .properties(vec![
+                Property::string("Script", "Id of the script used by the object. Optional. A PlayGroup script is generated when left empty."),
+                Property::choices(
+                    "RotationAxis",
+                    "Axis to rotate around",
+                    &[(0, "Z (Yaw)"), (1, "X (Roll)"), (2, "Y (Pitch)")],
+                )
+                .default("0"),
+                Property::float("Speed", "Rotation speed in degrees per second. Negative values rotate the other way.")
+                    .default("90"),
+            ]),
+        EntityClass::solid("func_rotating", "Continuously rotating activator").bases(&["Mover"]),
+        EntityClass::solid("func_door_rotating", "Activator which swings open and closed when used")
+            .bases(&["Mover"])
+            .properties(vec![
+                Property::float("Angle", "Degrees to swing when opened. Negative values swing the other way.")
+                    .default("90"),
+                Property::string("OpenSound", "Sound id played when opening").default("Door Stone Open"),
+                Property::string("CloseSound", "Sound id played when closing").default("Door Stone Close"),
+            ]),
+        EntityClass::base("Trigger", "Invisible volume checked against the player's position")
+            .properties(vec![
+                Property::string("RefId", "Ref Id for the trigger's activator. Optional."),
+                origin_property(),
+            ]),
+        EntityClass::solid("trigger_Script", "Runs script statements when the player enters the volume")
+            .bases(&["Trigger"])
+            .properties(vec![
+                Property::string("OnEnter", "Statements run on entering. Separate several with \\n. Optional."),
+                Property::string("Global", "Global variable set on entering. Optional."),
+                Property::integer("GlobalValue", "Value given to the global").default("1"),
+                Property::boolean("Once", "Only fire the first time the player enters"),
+            ]),
+        EntityClass::solid("trigger_Hurt", "Damages the player while inside the volume. Texture it with hurt.")
+            .bases(&["Trigger"])
+            .properties(vec![Property::float("Damage", "Health lost per second").default("10")]),
+        EntityClass::solid("item_Alchemy", "Base Potion Entity")
+            .bases(&["Referenceable", "MagicEffect1"])
+            .properties(vec![Property::flags(
+                "PotionFlags",
+                "Potion flags",
+                &[(1, "Auto Calculate Value")],
+            )]),
+        EntityClass::solid("item_Misc", "Base Miscellaneous Entity")
+            .bases(&["Referenceable"])
+            .properties(vec![Property::flags("MiscFlags", "Misc item flags", &[(1, "Key")])]),
+        EntityClass::solid("item_Apparatus", "Base Apparatus Entity")
+            .bases(&["Referenceable"])
+            .properties(vec![
+                Property::float("Quality", "Strength for creating alchemy ingredients. Multiplicative, so suggested to stay between 1 and 0.")
+                    .default("0.0"),
+                Property::indexed(
+                    "ApparatusType",
+                    "Type of apparatus",
+                    &["Mortar And Pestle", "Alembic", "Calcinator", "Retort"],
+                )
+                .default("0"),
+            ]),
+        EntityClass::solid("item_Book", "Base Book Entity")
+            .bases(&["Wearable"])
+            .properties(vec![
+                Property::indexed("BookType", "Book Type", &["Book", "Scroll"]).default("0"),
+                skill_property("Skill", "Skill raised by this book.", true),
+                Property::string("Text", "Text of the book. You probably shouldn't write this here, but, you can."),
+            ]),
+        EntityClass::solid("item_Ingredient", "Base Ingredient Entity")
+            .bases(&["Referenceable", "MagicEffect2"]),
+        EntityClass::solid("item_Light", "Brush Light")
+            .bases(&["Referenceable"])
+            .properties(vec![
+                Property::string("Sound", "Internal SoundID played by this light. Can only be determined through a Morrowind Construction set."),
+                Property::integer("Time", "Amount of time the light will last before fading. A value of zero indicates the light does not expire."),
+                Property::integer("Radius", "Radius of the light"),
+                Property::color("light_color", "Light Color"),
+                light_flags(true),
+            ]),
+        // Since lights can be carryable or not, we use this
+        // So that it can be a brush or point class
+        EntityClass::base("PointLightData", "Light Data").properties(vec![
+            Property::string("RefId", "Ref Id for the light. Optional. Lights without one are named after their place in the map, which changes as entities are added or removed."),
+            Property::string("Sound", "Internal SoundID played by this light. Can only be determined through a Morrowind Construction set."),
+            Property::string("Script", "Internal MWScript attached to this light. Can only be determined through a Morrowind Construction set."),
+            Property::string("Model", "Model shown for this light. Optional."),
+            Property::integer("Radius", "Light Radius").default("64"),
+            Property::color("light_color", "Light Color"),
+            light_flags(false),
+        ]),
+    ];
+
+    classes.extend([64, 128, 256, 512, 1024].iter().map(|radius| {
+        let half = radius / 2;
+        EntityClass::point(
+            &format!("Light_Point{radius}"),
+            &format!("{radius} Unit Light"),
+        )
+        .bases(&["PointLightData"])
+        .attribute(&format!(
+            "size(-{half} -{half} -{half}, {half} {half} {half})"
+        ))
+        .attribute(&format!("iconsprite(\"Textures/light{radius}.webp\")"))
+        .properties(vec![
+            Property::integer("Radius", "Light Radius").default(&radius.to_string())
+        ])
+    }));
+
+    classes.extend([
+        EntityClass::solid("world_Container", "Container type")
+            .bases(&["baseObject", "Lockable"])
+            .properties(vec![
+                script_property(),
+                Property::float("Encumbrance", "Total carrying capacity of the container. Optional. 0 means unlimited capacity.")
+                    .default("0.0"),
+                Property::flags(
+                    "ContainerFlags",
+                    "Container flags",
+                    &[(1, "Organic"), (2, "Respawns")],
+                ),
+            ])
+            .properties(inventory_properties(8)),
+        // Models are written by `morrobroom export-models --out <game path>/morrobroom`
+        EntityClass::point("world_Reference", "Placement of an existing base object")
+            .bases(&["Ownership", "Lockable"])
+            .attribute("size(-16 -16 -16, 16 16 16)")
+            .attribute("model({ \"path\": \"morrobroom/\" + RefId + \".obj\" })")
+            .properties(vec![
+                Property::string("RefId", "Ref Id of the base object to place")
+                    .default("furn_de_p_bench_03"),
+                Property::float("Scale", "Scale of this placement").default("1.0"),
+            ]),
+        EntityClass::base("MagicDefinition", "Magic Definition")
+            .attribute("size(-8 -8 -8, 8 8 8)")
+            .attribute("color(128 0 255)")
+            .bases(&["MagicEffect1"])
+            .properties(vec![
+                Property::string("RefId", "Ref Id for the record").default("sc_icarianflight_en"),
+                Property::integer("Cost", "Magicka or charge cost per use").default("0"),
+            ]),
+        EntityClass::point("def_Enchantment", "Enchantment definition. Defines a record only and is not placed in the cell.")
+            .bases(&["MagicDefinition"])
+            .properties(vec![
+                Property::indexed(
+                    "EnchantType",
+                    "Enchantment Type",
+                    &["Cast Once", "Cast On Strike", "Cast When Used", "Constant Effect"],
+                )
+                .default("0"),
+                Property::integer("Charge", "Maximum charge of the enchantment").default("0"),
+                Property::flags("EnchantFlags", "Enchantment flags", &[(1, "Auto Calculate")]),
+            ]),
+        EntityClass::point("def_Spell", "Spell definition. Defines a record only and is not placed in the cell.")
+            .bases(&["MagicDefinition"])
+            .properties(vec![
+                Property::string("Name", "Ingame readable name for the spell").default("Icarian Flight"),
+                Property::indexed(
+                    "SpellType",
+                    "Spell Type",
+                    &["Spell", "Ability", "Blight", "Disease", "Curse", "Power"],
+                )
+                .default("0"),
+                Property::flags(
+                    "SpellFlags",
+                    "Spell flags",
+                    &[(1, "Auto Calculate"), (2, "Player Start Spell"), (4, "Always Succeeds")],
+                ),
+            ]),
+        EntityClass::point("ambient_Sound", "Looping sound emitter. Creates a sound record and an invisible activator which plays it.")
+            .attribute("size(-8 -8 -8, 8 8 8)")
+            .attribute("model(\"Meshes/speaker.dae\")")
+            .properties(vec![
+                Property::string("RefId", "Ref Id for the sound record").default("ambient_wind"),
+                Property::string("Sound", "Sound file, relative to the Sound folder").default("Fx\\envrn\\wind.wav"),
+                Property::float("Volume", "Volume between 0 and 1").default("1.0"),
+                Property::integer("MinRange", "Range at which the sound starts to fade. Multiplied by the compile scale.").default("0"),
+                Property::integer("MaxRange", "Range past which the sound is inaudible. Multiplied by the compile scale.").default("255"),
+            ]),
+        EntityClass::base("Actor", "Actor")
+            .attribute("size(-16 -16 0, 16 16 128)")
+            .attribute("color(255 255 0)")
+            .properties(vec![
+                Property::string("RefId", "Ref Id for the actor").default("agronian guy"),
+                Property::string("Name", "Ingame readable name for the actor").default("Tarhiel"),
+                Property::string("Model", "Model used by the actor. Optional for NPCs, which use their race's body."),
+                script_property(),
+                Property::integer("Level", "Actor level").default("1"),
+                Property::integer("Gold", "Gold carried for bartering").default("0"),
+                Property::integer("Hello", "Distance at which the actor greets the player").default("30"),
+                Property::integer("Fight", "Likelihood of attacking the player").default("30"),
+                Property::integer("Flee", "Likelihood of fleeing combat").default("30"),
+                Property::integer("Alarm", "Likelihood of reporting crimes").default("0"),
+            ])
+            .properties(inventory_properties(8))
+            .properties(spell_properties(8)),
+        EntityClass::point("actor_NPC", "NPC definition and placement. Stats are auto calculated.")
+            .bases(&["Actor"])
+            .properties(vec![
+                Property::string("Race", "Race of the NPC").default("Dark Elf"),
+                Property::string("Class", "Class of the NPC").default("Commoner"),
+                Property::string("Faction", "Faction the NPC belongs to. Optional."),
+                Property::integer("Rank", "Rank within the faction").default("0"),
+                Property::string("Head", "Head body part").default("b_n_dark elf_m_head_01"),
+                Property::string("Hair", "Hair body part").default("b_n_dark elf_m_hair_01"),
+                Property::integer("Disposition", "Starting disposition towards the player").default("50"),
+                Property::integer("Reputation", "Reputation of the NPC").default("0"),
+                Property::flags(
+                    "NpcFlags",
+                    "NPC flags",
+                    &[(1, "Female"), (2, "Essential"), (4, "Respawns")],
+                ),
+            ]),
+        EntityClass::point("actor_Creature", "Creature definition and placement")
+            .bases(&["Actor"])
+            .properties(vec![
+                Property::indexed("CreatureType", "Creature Type", &["Creature", "Daedra", "Undead", "Humanoid"])
+                    .default("0"),
+                Property::integer("Health", "Maximum health").default("50"),
+                Property::integer("Magicka", "Maximum magicka").default("0"),
+                Property::integer("Fatigue", "Maximum fatigue").default("100"),
+                Property::integer("Soul", "Soul gem value").default("0"),
+                Property::integer("Combat", "Combat skill").default("30"),
+                Property::integer("Magic", "Magic skill").default("30"),
+                Property::integer("Stealth", "Stealth skill").default("30"),
+                Property::integer("Attack1Min", "Minimum damage of the first attack").default("1"),
+                Property::integer("Attack1Max", "Maximum damage of the first attack").default("5"),
+                Property::integer("Attack2Min", "Minimum damage of the second attack").default("0"),
+                Property::integer("Attack2Max", "Maximum damage of the second attack").default("0"),
+                Property::integer("Attack3Min", "Minimum damage of the third attack").default("0"),
+                Property::integer("Attack3Max", "Maximum damage of the third attack").default("0"),
+                Property::flags(
+                    "CreatureFlags",
+                    "Creature flags",
+                    &[
+                        (1, "Biped"), (2, "Respawns"), (4, "Weapon And Shield"), (16, "Swims"),
+                        (32, "Flies"), (64, "Walks"), (128, "Essential"),
+                    ],
+                ),
+            ])
+            .properties(
+                ATTRIBUTES
+                    .iter()
+                    .map(|attribute| Property::integer(attribute, attribute).default("50"))
+                    .collect(),
+            ),
+        EntityClass::point("world_CreatureList", "Leveled Creature List")
+            .properties(leveled_list_properties("Creature", "creature")),
+        EntityClass::point("world_ItemList", "Leveled Item List")
+            .properties(leveled_list_properties("Item", "item")),
+    ]);
+
+    classes
+}
+
+fn material_properties() -> Vec<Property> {
+    vec![
+        Property::color("Material_Emissive_color", "Color emitted by the brush").default("1.0 0 0"),
+        Property::color("Material_Ambient_color", "Ambient color of the brush").default("0 1.0 0"),
+        Property::color("Material_Diffuse_color", "Diffuse color of the brush").default("0 0 1.0"),
+        Property::float("Material_Alpha", "Material Transparency").default("1.0"),
+        Property::boolean(
+            "Material_Alpha_UseBlend",
+            "Use alpha blending for this brush",
+        ),
+        Property::choices(
+            "Material_Alpha_BlendSourceMode",
+            "Mode to use for alpha blending on the light source",
+            &[
+                (0, "One"),
+                (2, "Zero"),
+                (4, "Source Color"),
+                (6, "One Minus Source Color"),
+                (8, "Destination Color"),
+                (10, "One Minus Destination Color"),
+                (12, "Source Alpha"),
+                (14, "One Minus Source Alpha"),
+                (16, "Destination Alpha"),
+                (18, "One Minus Destination Alpha"),
+                (20, "Source Alpha Saturate"),
+            ],
+        )
+        .default("0"),
+        Property::choices(
+            "Material_Alpha_BlendDestinationMode",
+            "Mode to use for alpha blending on the light destination",
+            &[
+                (0, "One"),
+                (32, "Zero"),
+                (64, "Source Color"),
+                (96, "One Minus Source Color"),
+                (128, "Destination Color"),
+                (160, "One Minus Destination Color"),
+                (192, "Source Alpha"),
+                (224, "One Minus Source Alpha"),
+                (256, "Destination Alpha"),
+                (288, "One Minus Destination Alpha"),
+                (320, "Source Alpha Saturate"),
+            ],
+        )
+        .default("0"),
+        Property::choices(
+            "Material_Alpha_TestEnable",
+            "Use alpha testing for this brush",
+            &[(0, "False"), (512, "True")],
+        )
+        .default("0"),
+        Property::choices(
+            "Material_Alpha_TestFunction",
+            "Comparison used when alpha testing this brush",
+            &[
+                (0, "Always"),
+                (1024, "Less"),
+                (2048, "Equal"),
+                (3072, "Less Than Or Equal"),
+                (4096, "Greater Than"),
+                (5120, "Not Equal"),
+                (6144, "Greater Than Or Equal"),
+                (7168, "Never"),
+            ],
+        )
+        .default("0"),
+        Property::integer(
+            "Material_Alpha_TestThreshold",
+            "Threshold between 0 and 255 to use against the background when alpha testing",
+        )
+        .default("128"),
+        Property::choices(
+            "Material_Alpha_NoSort",
+            "Disable triangle sorting for this object",
+            &[(0, "False"), (8192, "True")],
+        )
+        .default("0"),
+        Property::float(
+            "Material_UVScroll_U",
+            "Texture repeats scrolled per second along U",
+        )
+        .default("0"),
+        Property::float(
+            "Material_UVScroll_V",
+            "Texture repeats scrolled per second along V",
+        )
+        .default("0"),
+        Property::float(
+            "Material_UVScroll_TileU",
+            "Texture tiling along U while animated",
+        )
+        .default("1.0"),
+        Property::float(
+            "Material_UVScroll_TileV",
+            "Texture tiling along V while animated",
+        )
+        .default("1.0"),
+        Property::float(
+            "Material_UVScroll_Period",
+            "Length in seconds of one scroll loop",
+        )
+        .default("1.0"),
+    ]
+}
+
+fn origin_property() -> Property {
+    Property::string("origin", "Pivot point of the object, in map units. Optional. Overrides any brush textured with origin, otherwise the center of the brushes is used.")
+}
+
+fn script_property() -> Property {
+    Property::string("Script", "Id of the script used by the object. Optional. Be warned that all instances of this refId will share the script.")
+        .default("fallingScript")
+}
+
+fn skill_property(name: &str, description: &str, allow_none: bool) -> Property {
+    let mut choices: Vec<(i32, &str)> = SKILLS
+        .iter()
+        .enumerate()
+        .map(|(index, skill)| (index as i32, *skill))
+        .collect();
+
+    if allow_none {
+        choices.insert(0, (-1, "None"));
+    }
+
+    Property::choices(name, description, &choices).default(if allow_none { "-1" } else { "0" })
+}
+
+fn light_flags(carryable: bool) -> Property {
+    let mut flags = vec![(1, "Dynamic")];
+    if carryable {
+        flags.push((2, "Carryable"));
+    }
+    flags.extend([
+        (4, "Negative"),
+        (8, "Flicker"),
+        (16, "Fire"),
+        (32, "OffByDefault"),
+        (64, "FlickerSLOW"),
+        (128, "Pulse"),
+        (256, "PulseSlow"),
+    ]);
+    Property::flags("LightFlags", "Light flags", &flags)
+}
+
+/// Read by game_object::collect_effects
+pub fn effect_properties(count: usize) -> Vec<Property> {
+    let mut effects: Vec<(i32, &str)> = vec![(-1, "None")];
+    effects.extend(
+        MAGIC_EFFECTS
+            .iter()
+            .enumerate()
+            .map(|(index, effect)| (index as i32, *effect)),
+    );
+
+    (1..=count)
+        .flat_map(|index| {
+            vec![
+                Property::choices(
+                    &format!("Effect_{index}_MagicType"),
+                    &format!("Effect {index} Type"),
+                    &effects,
+                )
+                .default("-1"),
+                Property::indexed(
+                    &format!("Effect_{index}_Attribute"),
+                    "Attribute modified by magic effect. Ignored if effect type does not use an attribute.",
+                    ATTRIBUTES,
+                )
+                .default("0"),
+                skill_property(
+                    &format!("Effect_{index}_Skill"),
+                    "Skill modified by magic effect. Ignored if effect type does not use a skill.",
+                    false,
+                ),
+                Property::indexed(
+                    &format!("Effect_{index}_Range"),
+                    "Spell Target Type",
+                    &["Self", "Touch", "Target"],
+                )
+                .default("0"),
+                Property::integer(&format!("Effect_{index}_Area"), "Area of effect").default("0"),
+                Property::integer(&format!("Effect_{index}_Duration"), "Effect Duration").default("0"),
+                Property::integer(
+                    &format!("Effect_{index}_Magnitude"),
+                    "Fixed magnitude. Overrides the minimum and maximum magnitude when set.",
+                ),
+                Property::integer(&format!("Effect_{index}_MagnitudeMin"), "Minimum Magnitude")
+                    .default("0"),
+                Property::integer(&format!("Effect_{index}_MagnitudeMax"), "Maximum Magnitude")
+                    .default("0"),
+            ]
+        })
+        .collect()
+}
+
+/// Read by game_object::collect_biped_objects
+pub fn biped_properties(count: usize) -> Vec<Property> {
+    (1..=count)
+        .flat_map(|index| {
+            vec![
+                Property::indexed(&format!("SlotType{index}"), "Slot Used", BIPED_SLOTS)
+                    .default("0"),
+                Property::string(
+                    &format!("male_part{index}"),
+                    &format!("Body part used for male bodies, slot {index}"),
+                ),
+                Property::string(
+                    &format!("female_part{index}"),
+                    &format!("Body part used for female bodies, slot {index}"),
+                ),
+                Property::string(
+                    &format!("male_mesh{index}"),
+                    &format!(
+                        "Mesh for a body part generated for male bodies, slot {index}. Optional."
+                    ),
+                ),
+                Property::string(
+                    &format!("female_mesh{index}"),
+                    &format!(
+                        "Mesh for a body part generated for female bodies, slot {index}. Optional."
+                    ),
+                ),
+            ]
+        })
+        .collect()
+}
+
+/// Read by game_object::collect_contained_objects
+pub fn inventory_properties(count: usize) -> Vec<Property> {
+    (1..=count)
+        .flat_map(|index| {
+            vec![
+                Property::string(&format!("Item{index}_Id"), "Ref Id of contained object"),
+                Property::integer(
+                    &format!("Item{index}_Count"),
+                    "Number of contained instances",
+                )
+                .default("1"),
+            ]
+        })
+        .collect()
+}
+
+/// Read by scatter::scatter
+fn scatter_properties(count: usize) -> Vec<Property> {
+    (1..=count)
+        .map(|index| {
+            Property::string(
+                &format!("Object{index}_Id"),
+                &format!("Ref Id of an object to scatter, slot {index}"),
+            )
+        })
+        .collect()
+}
+
+/// Read by game_object::collect_spells
+pub fn spell_properties(count: usize) -> Vec<Property> {
+    (1..=count)
+        .map(|index| {
+            Property::string(
+                &format!("Spell{index}_Id"),
+                &format!("Spell, ability or power known by the actor, slot {index}"),
+            )
+        })
+        .collect()
+}
+
+/// Read by game_object::collect_list_creatures and collect_list_items
+fn leveled_list_properties(prefix: &str, noun: &str) -> Vec<Property> {
+    let mut properties = vec![
+        Property::string("RefId", "Ref Id for the object").default("agronian guy"),
+        Property::integer("Chance_None", "Chance nothing will spawn").default("0"),
+        Property::boolean(
+            "Spawn_From_All_Levels",
+            "Calculate from all levels below the player's level",
+        ),
+    ];
+
+    properties.extend((1..=10).flat_map(|index| {
+        vec![
+            Property::string(
+                &format!("{prefix}_{index}_Id"),
+                &format!("Ref Id of contained {noun}"),
+            ),
+            Property::integer(
+                &format!("{prefix}_{index}_PlayerLevel"),
+                "Player level required to spawn this instance",
+            )
+            .default("1"),
+        ]
+    }));
+
+    properties
+}
+
+/// All property names usable on a class, including those inherited from its bases
+pub fn known_properties(classes: &[EntityClass], classname: &str) -> Option<HashSet<String>> {
+    let class = classes.iter().find(|class| class.name == classname)?;
+
+    let mut properties: HashSet<String> = class
+        .properties
+        .iter()
+        .map(|property| property.name.clone())
+        .collect();
+
+    for base in &class.bases {
+        if let Some(base_properties) = known_properties(classes, base) {
+            properties.extend(base_properties);
+        }
+    }
+
+    properties.extend(ENGINE_PROPERTIES.iter().map(|name| name.to_string()));
+    if classname == "worldspawn" {
+        properties.extend(
+            WORLDSPAWN_EDITOR_PROPERTIES
+                .iter()
+                .map(|name| name.to_string()),
+        );
+    }
+
+    Some(properties)
+}
+
+/// The default the fgd gives a property, which trenchbroom leaves out of the map
+pub fn property_default(
+    classes: &[EntityClass],
+    classname: &str,
+    property: &str,
+) -> Option<String> {
+    let class = classes.iter().find(|class| class.name == classname)?;
+
+    class
+        .properties
+        .iter()
+        .find(|class_property| class_property.name == property)
+        .and_then(|class_property| class_property.default.clone())
+        .or_else(|| {
+            class
+                .bases
+                .iter()
+                .find_map(|base| property_default(classes, base, property))
+        })
+}
+
+/// Mins and maxs of the box trenchbroom draws for a point class, from its own or its bases' size
+pub fn hull(classes: &[EntityClass], classname: &str) -> Option<([f32; 3], [f32; 3])> {
+    let class = classes.iter().find(|class| class.name == classname)?;
+
+    let size = class.attributes.iter().find_map(|attribute| {
+        let corners = attribute.strip_prefix("size(")?.strip_suffix(')')?;
+        let corners: Vec<f32> = corners
+            .split([' ', ','])
+            .filter_map(|axis| axis.parse::<f32>().ok())
+            .collect();
+
+        match corners[..] {
+            [min_x, min_y, min_z, max_x, max_y, max_z] => {
+                Some(([min_x, min_y, min_z], [max_x, max_y, max_z]))
+            }
+            _ => None,
+        }
+    });
+
+    size.or_else(|| class.bases.iter().find_map(|base| hull(classes, base)))
+}
+
+pub fn render(classes: &[EntityClass]) -> String {
+    let mut fgd = String::from(
+        "// Generated by `morrobroom fgd` from src/fgd.rs. Edit the schema there instead.\n",
+    );
+
+    for class in classes {
+        let class_type = match class.class_type {
+            ClassType::Base => "@BaseClass",
+            ClassType::Solid => "@SolidClass",
+            ClassType::Point => "@PointClass",
+        };
+
+        let mut header = class_type.to_string();
+        if !class.bases.is_empty() {
+            let _ = write!(header, " base({})", class.bases.join(", "));
+        }
+        for attribute in &class.attributes {
+            let _ = write!(header, " {attribute}");
+        }
+
+        let _ = writeln!(
+            fgd,
+            "\n{header} = {} : \"{}\"\n[",
+            class.name, class.description
+        );
+
+        for property in &class.properties {
+            render_property(&mut fgd, property);
+        }
+
+        let _ = writeln!(fgd, "]");
+    }
+
+    // Trenchbroom ships its own fgds with windows line endings, and so does morrobroom
+    fgd.replace('\n', "\r\n")
+}
+
+fn render_property(fgd: &mut String, property: &Property) {
+    let (type_name, quote_default) = match property.kind {
+        PropertyType::String => ("string", true),
+        PropertyType::Integer => ("integer", false),
+        PropertyType::Float => ("float", true),
+        PropertyType::Color => ("color", true),
+        PropertyType::Choices(_) => ("choices", false),
+        PropertyType::Flags(_) => ("flags", false),
+    };
+
+    let mut line = format!(
+        "    {}({type_name}) : \"{}\"",
+        property.name, property.description
+    );
+
+    if let Some(default) = &property.default {
+        match quote_default {
+            true => {
+                let _ = write!(line, " : \"{default}\"");
+            }
+            false => {
+                let _ = write!(line, " : {default}");
+            }
+        }
+    }
+
+    match &property.kind {
+        PropertyType::Choices(choices) => {
+            let _ = writeln!(fgd, "{line} =\n    [");
+            for (value, label) in choices {
+                let _ = writeln!(fgd, "        {value} : \"{label}\"");
+            }
+            let _ = writeln!(fgd, "    ]");
+        }
+        PropertyType::Flags(flags) => {
+            let _ = writeln!(fgd, "    {}(flags) =\n    [", property.name);
+            for (value, label) in flags {
+                let _ = writeln!(fgd, "        {value} : \"{label}\" : 0");
+            }
+            let _ = writeln!(fgd, "    ]");
+        }
+        _ => {
+            let _ = writeln!(fgd, "{line}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_fgd_matches_the_schema() {
+        assert!(
+            include_str!("../resources/Morrowind.fgd") == render(&classes()),
+            "resources/Morrowind.fgd is out of date. Regenerate it with `morrobroom fgd --out resources/Morrowind.fgd`."
+        );
+    }
+}
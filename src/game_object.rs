@@ -1,12 +1,16 @@
-use crate::surfaces;
+use crate::{fgd, surfaces};
 use std::collections::HashMap;
 use tes3::esp::{
-    Activator, Alchemy, AlchemyData, AlchemyFlags, Apparatus, ApparatusData, Armor, ArmorData,
-    AtmosphereData, AttributeId, AttributeId2, BipedObject, Book, BookData, BookType, Cell,
-    CellFlags, Container, ContainerFlags, Effect, EffectId, EffectId2, EffectRange, Ingredient,
-    IngredientData, LeveledCreature, LeveledCreatureFlags, LeveledItem, LeveledItemFlags, Light,
-    LightData, LightFlags, MiscItem, MiscItemData, MiscItemFlags, ObjectFlags, SkillId, SkillId2,
-    TES3Object,
+    Activator, AiData, Alchemy, AlchemyData, AlchemyFlags, Apparatus, ApparatusData, Armor,
+    ArmorData, AtmosphereData, AttributeId, AttributeId2, BipedObject, BodyPart, BodyPartData,
+    BodyPartFlags, BodyPartType, Book, BookData, BookType, Cell, CellFlags, Clothing, ClothingData,
+    Container, ContainerFlags, Creature, CreatureData, CreatureFlags, CreatureType, EditorId,
+    Effect, EffectId, EffectId2, EffectRange, EnchantType, Enchanting, EnchantingData,
+    EnchantingFlags, Ingredient, IngredientData, LeveledCreature, LeveledCreatureFlags,
+    LeveledItem, LeveledItemFlags, Light, LightData, LightFlags, Lockpick, LockpickData, MiscItem,
+    MiscItemData, MiscItemFlags, Npc, NpcData, NpcFlags, ObjectFlags, Probe, ProbeData, RepairItem,
+    RepairItemData, Script, SkillId, SkillId2, Sound, SoundData, Spell, SpellData, SpellFlags,
+    SpellType, TES3Object, Weapon, WeaponData, WeaponFlags,
 };
 
 pub fn activator(
@@ -58,6 +62,12 @@ pub fn armor(
     ref_id: &str,
     mesh_name: &str,
 ) -> TES3Object {
+    let armor_type = get_prop("ArmorType", entity_props)
+        .parse::<u32>()
+        .unwrap_or_default();
+
+    validate_armor_slots(armor_type, &collect_biped_slots(entity_props), ref_id);
+
     TES3Object::Armor(Armor {
         flags: ObjectFlags::default(),
         id: ref_id.to_owned(),
@@ -66,13 +76,9 @@ pub fn armor(
         mesh: mesh_name.to_owned(),
         icon: get_prop("Icon", entity_props),
         enchanting: get_prop("Enchantment", entity_props),
-        biped_objects: collect_biped_objects(entity_props),
+        biped_objects: collect_biped_objects(entity_props, ref_id),
         data: ArmorData {
-            armor_type: get_prop("ArmorType", entity_props)
-                .parse::<u32>()
-                .unwrap_or_default()
-                .try_into()
-                .expect("Invalid Armor Type!"),
+            armor_type: armor_type.try_into().expect("Invalid Armor Type!"),
             armor_rating: get_prop("ArmorRating", entity_props)
                 .parse::<u32>()
                 .unwrap_or_default(),
@@ -92,6 +98,51 @@ pub fn armor(
     })
 }
 
+/// Body parts are only generated for slots which have a mesh assigned.
+/// Slots which only name a part refer to an existing BodyPart record instead.
+pub fn body_parts(
+    entity_props: &HashMap<&String, &String>,
+    ref_id: &str,
+    part_type: BodyPartType,
+) -> Vec<TES3Object> {
+    let mut body_parts = Vec::new();
+
+    for (count, slot) in collect_biped_slots(entity_props) {
+        for (sex, flags) in [
+            ("male", BodyPartFlags::empty()),
+            ("female", BodyPartFlags::FEMALE),
+        ] {
+            let mesh = get_prop(&format!("{sex}_mesh{count}"), entity_props);
+
+            if mesh.is_empty() {
+                continue;
+            }
+
+            let part = BIPED_SLOT_PARTS
+                .get(slot as usize)
+                .copied()
+                .flatten()
+                .expect(&format!(
+                    "Biped slot {slot} on {ref_id} has no matching body part type and must use an existing body part!"
+                ));
+
+            body_parts.push(TES3Object::BodyPart(BodyPart {
+                id: biped_part_id(entity_props, ref_id, count, sex),
+                mesh,
+                data: BodyPartData {
+                    part: part.try_into().expect("Invalid Body Part Type!"),
+                    flags,
+                    part_type,
+                    ..Default::default()
+                },
+                ..Default::default()
+            }));
+        }
+    }
+
+    body_parts
+}
+
 pub fn book(entity_props: &HashMap<&String, &String>, ref_id: &str, mesh_name: &str) -> TES3Object {
     TES3Object::Book(Book {
         flags: ObjectFlags::default(),
@@ -177,6 +228,39 @@ pub fn cell(entity_props: &HashMap<&String, &String>) -> Cell {
     }
 }
 
+pub fn clothing(
+    entity_props: &HashMap<&String, &String>,
+    ref_id: &str,
+    mesh_name: &str,
+) -> TES3Object {
+    TES3Object::Clothing(Clothing {
+        flags: ObjectFlags::default(),
+        id: ref_id.to_owned(),
+        name: get_prop("Name", entity_props),
+        script: get_prop("Script", entity_props),
+        mesh: mesh_name.to_owned(),
+        icon: get_prop("Icon", entity_props),
+        enchanting: get_prop("Enchantment", entity_props),
+        biped_objects: collect_biped_objects(entity_props, ref_id),
+        data: ClothingData {
+            clothing_type: get_prop("ClothingType", entity_props)
+                .parse::<u32>()
+                .unwrap_or_default()
+                .try_into()
+                .expect("Invalid Clothing Type!"),
+            weight: get_prop("Weight", entity_props)
+                .parse::<f32>()
+                .unwrap_or_default(),
+            value: get_prop("Value", entity_props)
+                .parse::<u16>()
+                .unwrap_or_default(),
+            enchantment: get_prop("EnchantmentPoints", entity_props)
+                .parse::<u16>()
+                .unwrap_or_default(),
+        },
+    })
+}
+
 pub fn container(
     entity_props: &HashMap<&String, &String>,
     ref_id: &str,
@@ -202,6 +286,61 @@ pub fn container(
     })
 }
 
+/// Creatures always carry full stats, so nothing is auto calculated
+pub fn creature(entity_props: &HashMap<&String, &String>, ref_id: &str) -> TES3Object {
+    // Trenchbroom doesn't write defaults out, so unset stats use the fgd's
+    let stat = |prop_name: &str, default: u32| {
+        get_prop(prop_name, entity_props)
+            .parse::<u32>()
+            .unwrap_or(default)
+    };
+
+    let mut attributes = [0; 8];
+    for (attribute, name) in attributes.iter_mut().zip(fgd::ATTRIBUTES) {
+        *attribute = match get_prop(name, entity_props).parse::<u32>() {
+            Ok(value) => value,
+            Err(_) => 50,
+        };
+    }
+
+    TES3Object::Creature(Creature {
+        flags: ObjectFlags::default(),
+        id: ref_id.to_owned(),
+        name: get_prop("Name", entity_props),
+        script: get_prop("Script", entity_props),
+        mesh: get_prop("Model", entity_props),
+        // Every vanilla creature sets 0x8, which the fgd doesn't offer
+        creature_flags: CreatureFlags::from_bits(
+            match get_prop("CreatureFlags", entity_props).parse::<u32>() {
+                Ok(flags) => flags | 0x8,
+                Err(_) => 0x48, // Walks
+            },
+        )
+        .expect("Invalid Creature Flags!"),
+        data: CreatureData {
+            creature_type: CreatureType::try_from(stat("CreatureType", 0))
+                .expect("Invalid Creature Type!"),
+            level: stat("Level", 1),
+            attributes,
+            health: stat("Health", 50),
+            magicka: stat("Magicka", 0),
+            fatigue: stat("Fatigue", 100),
+            soul: stat("Soul", 0),
+            combat: stat("Combat", 30),
+            magic: stat("Magic", 30),
+            stealth: stat("Stealth", 30),
+            attack1: (stat("Attack1Min", 1), stat("Attack1Max", 5)),
+            attack2: (stat("Attack2Min", 0), stat("Attack2Max", 0)),
+            attack3: (stat("Attack3Min", 0), stat("Attack3Max", 0)),
+            gold: stat("Gold", 0),
+        },
+        inventory: collect_contained_objects(entity_props),
+        spells: collect_spells(entity_props),
+        ai_data: collect_ai_data(entity_props),
+        ..Default::default()
+    })
+}
+
 pub fn creature_list(entity_props: &HashMap<&String, &String>, ref_id: &str) -> TES3Object {
     TES3Object::LeveledCreature(LeveledCreature {
         flags: ObjectFlags::default(),
@@ -236,6 +375,35 @@ pub fn item_list(entity_props: &HashMap<&String, &String>, ref_id: &str) -> TES3
     })
 }
 
+/// Enchantments only carry effects, so they are defined by point entities which are never placed
+pub fn enchantment(entity_props: &HashMap<&String, &String>, ref_id: &str) -> TES3Object {
+    TES3Object::Enchanting(Enchanting {
+        flags: ObjectFlags::default(),
+        id: ref_id.to_owned(),
+        data: EnchantingData {
+            enchant_type: EnchantType::try_from(
+                get_prop("EnchantType", entity_props)
+                    .parse::<u32>()
+                    .unwrap_or_default(),
+            )
+            .expect("Invalid Enchantment Type!"),
+            cost: get_prop("Cost", entity_props)
+                .parse::<u32>()
+                .unwrap_or_default(),
+            max_charge: get_prop("Charge", entity_props)
+                .parse::<u32>()
+                .unwrap_or_default(),
+            flags: EnchantingFlags::from_bits(
+                get_prop("EnchantFlags", entity_props)
+                    .parse::<u32>()
+                    .unwrap_or_default(),
+            )
+            .expect("Invalid Enchantment Flags!"),
+        },
+        effects: collect_effects(entity_props, 8),
+    })
+}
+
 pub fn ingredient(
     entity_props: &HashMap<&String, &String>,
     ref_id: &str,
@@ -288,6 +456,53 @@ pub fn ingredient(
     })
 }
 
+/// Stats are always auto calculated from the class, race and level
+pub fn npc(entity_props: &HashMap<&String, &String>, ref_id: &str) -> TES3Object {
+    TES3Object::Npc(Npc {
+        flags: ObjectFlags::default(),
+        id: ref_id.to_owned(),
+        name: get_prop("Name", entity_props),
+        script: get_prop("Script", entity_props),
+        mesh: get_prop("Model", entity_props),
+        race: get_prop("Race", entity_props),
+        class: get_prop("Class", entity_props),
+        faction: get_prop("Faction", entity_props),
+        head: get_prop("Head", entity_props),
+        hair: get_prop("Hair", entity_props),
+        npc_flags: NpcFlags::from_bits(
+            get_prop("NpcFlags", entity_props)
+                .parse::<u32>()
+                .unwrap_or_default(),
+        )
+        .expect("Invalid NPC Flags!")
+            | NpcFlags::AUTO_CALCULATE,
+        data: NpcData {
+            level: match get_prop("Level", entity_props).parse::<i16>() {
+                Ok(level) => level,
+                Err(_) => 1,
+            },
+            stats: None,
+            disposition: match get_prop("Disposition", entity_props).parse::<i8>() {
+                Ok(disposition) => disposition,
+                Err(_) => 50,
+            },
+            reputation: get_prop("Reputation", entity_props)
+                .parse::<i8>()
+                .unwrap_or_default(),
+            rank: get_prop("Rank", entity_props)
+                .parse::<i8>()
+                .unwrap_or_default(),
+            gold: get_prop("Gold", entity_props)
+                .parse::<u32>()
+                .unwrap_or_default(),
+        },
+        inventory: collect_contained_objects(entity_props),
+        spells: collect_spells(entity_props),
+        ai_data: collect_ai_data(entity_props),
+        ..Default::default()
+    })
+}
+
 pub fn point_light(
     entity_props: &HashMap<&String, &String>,
     scale_mode: &f32,
@@ -361,6 +576,35 @@ pub fn light(
     })
 }
 
+pub fn lockpick(
+    entity_props: &HashMap<&String, &String>,
+    ref_id: &str,
+    mesh_name: &str,
+) -> TES3Object {
+    TES3Object::Lockpick(Lockpick {
+        flags: ObjectFlags::default(),
+        id: ref_id.to_owned(),
+        name: get_prop("Name", entity_props),
+        script: get_prop("Script", entity_props),
+        mesh: mesh_name.to_owned(),
+        icon: get_prop("Icon", entity_props),
+        data: LockpickData {
+            weight: get_prop("Weight", entity_props)
+                .parse::<f32>()
+                .unwrap_or_default(),
+            value: get_prop("Value", entity_props)
+                .parse::<u32>()
+                .unwrap_or_default(),
+            quality: get_prop("Quality", entity_props)
+                .parse::<f32>()
+                .unwrap_or_default(),
+            uses: get_prop("Uses", entity_props)
+                .parse::<u32>()
+                .unwrap_or_default(),
+        },
+    })
+}
+
 pub fn misc(entity_props: &HashMap<&String, &String>, ref_id: &str, mesh_name: &str) -> TES3Object {
     TES3Object::MiscItem(MiscItem {
         flags: ObjectFlags::default(),
@@ -416,6 +660,233 @@ pub fn potion(
     })
 }
 
+pub fn probe(
+    entity_props: &HashMap<&String, &String>,
+    ref_id: &str,
+    mesh_name: &str,
+) -> TES3Object {
+    TES3Object::Probe(Probe {
+        flags: ObjectFlags::default(),
+        id: ref_id.to_owned(),
+        name: get_prop("Name", entity_props),
+        script: get_prop("Script", entity_props),
+        mesh: mesh_name.to_owned(),
+        icon: get_prop("Icon", entity_props),
+        data: ProbeData {
+            weight: get_prop("Weight", entity_props)
+                .parse::<f32>()
+                .unwrap_or_default(),
+            value: get_prop("Value", entity_props)
+                .parse::<u32>()
+                .unwrap_or_default(),
+            quality: get_prop("Quality", entity_props)
+                .parse::<f32>()
+                .unwrap_or_default(),
+            uses: get_prop("Uses", entity_props)
+                .parse::<u32>()
+                .unwrap_or_default(),
+        },
+    })
+}
+
+pub fn repair_item(
+    entity_props: &HashMap<&String, &String>,
+    ref_id: &str,
+    mesh_name: &str,
+) -> TES3Object {
+    TES3Object::RepairItem(RepairItem {
+        flags: ObjectFlags::default(),
+        id: ref_id.to_owned(),
+        name: get_prop("Name", entity_props),
+        script: get_prop("Script", entity_props),
+        mesh: mesh_name.to_owned(),
+        icon: get_prop("Icon", entity_props),
+        data: RepairItemData {
+            weight: get_prop("Weight", entity_props)
+                .parse::<f32>()
+                .unwrap_or_default(),
+            value: get_prop("Value", entity_props)
+                .parse::<u32>()
+                .unwrap_or_default(),
+            uses: get_prop("Uses", entity_props)
+                .parse::<u32>()
+                .unwrap_or_default(),
+            quality: get_prop("Quality", entity_props)
+                .parse::<f32>()
+                .unwrap_or_default(),
+        },
+    })
+}
+
+/// Ranges are stored as a single byte, so they are clamped after scaling
+pub fn sound(
+    entity_props: &HashMap<&String, &String>,
+    scale_mode: &f32,
+    ref_id: &str,
+) -> TES3Object {
+    let range = |prop_name: &str, default: f32| {
+        (get_prop(prop_name, entity_props)
+            .parse::<f32>()
+            .unwrap_or(default)
+            * scale_mode)
+            .round()
+            .clamp(0.0, 255.0) as u8
+    };
+
+    TES3Object::Sound(Sound {
+        flags: ObjectFlags::default(),
+        id: ref_id.to_owned(),
+        sound_path: get_prop("Sound", entity_props),
+        data: SoundData {
+            volume: (get_prop("Volume", entity_props)
+                .parse::<f32>()
+                .unwrap_or(1.0)
+                .clamp(0.0, 1.0)
+                * 255.0)
+                .round() as u8,
+            range: (range("MinRange", 0.0), range("MaxRange", 255.0)),
+        },
+    })
+}
+
+/// Attaches a script to any object type which can carry one
+pub fn set_script(object: &mut TES3Object, script_id: &str) {
+    match script_field(object) {
+        Some(script) => *script = script_id.to_owned(),
+        None => println!(
+            "Warning! {} cannot carry a script, so {script_id} was not attached.",
+            object.editor_id()
+        ),
+    }
+}
+
+pub fn script_field(object: &mut TES3Object) -> Option<&mut String> {
+    match object {
+        TES3Object::Activator(object) => Some(&mut object.script),
+        TES3Object::Alchemy(object) => Some(&mut object.script),
+        TES3Object::Apparatus(object) => Some(&mut object.script),
+        TES3Object::Armor(object) => Some(&mut object.script),
+        TES3Object::Book(object) => Some(&mut object.script),
+        TES3Object::Clothing(object) => Some(&mut object.script),
+        TES3Object::Container(object) => Some(&mut object.script),
+        TES3Object::Ingredient(object) => Some(&mut object.script),
+        TES3Object::Light(object) => Some(&mut object.script),
+        TES3Object::Lockpick(object) => Some(&mut object.script),
+        TES3Object::MiscItem(object) => Some(&mut object.script),
+        TES3Object::Probe(object) => Some(&mut object.script),
+        TES3Object::RepairItem(object) => Some(&mut object.script),
+        TES3Object::Weapon(object) => Some(&mut object.script),
+        _ => None,
+    }
+}
+
+/// Restarts the loop whenever the cell is loaded again
+pub fn sound_loop_script(script_id: &str, sound_id: &str) -> String {
+    format!(
+        "Begin {script_id}\r\n\
+         \r\n\
+         if ( GetSoundPlaying \"{sound_id}\" == 0 )\r\n\
+         \tPlayLoopSound3D \"{sound_id}\"\r\n\
+         endif\r\n\
+         \r\n\
+         End {script_id}\r\n"
+    )
+}
+
+pub fn spell(entity_props: &HashMap<&String, &String>, ref_id: &str) -> TES3Object {
+    TES3Object::Spell(Spell {
+        flags: ObjectFlags::default(),
+        id: ref_id.to_owned(),
+        name: get_prop("Name", entity_props),
+        data: SpellData {
+            spell_type: SpellType::try_from(
+                get_prop("SpellType", entity_props)
+                    .parse::<u32>()
+                    .unwrap_or_default(),
+            )
+            .expect("Invalid Spell Type!"),
+            cost: get_prop("Cost", entity_props)
+                .parse::<u32>()
+                .unwrap_or_default(),
+            flags: SpellFlags::from_bits(
+                get_prop("SpellFlags", entity_props)
+                    .parse::<u32>()
+                    .unwrap_or_default(),
+            )
+            .expect("Invalid Spell Flags!"),
+        },
+        effects: collect_effects(entity_props, 8),
+    })
+}
+
+/// Scripts are stored as source text, without compiled bytecode
+pub fn script(script_id: &str, text: &str) -> TES3Object {
+    TES3Object::Script(Script {
+        id: script_id.to_owned(),
+        text: text.to_owned(),
+        ..Default::default()
+    })
+}
+
+pub fn weapon(
+    entity_props: &HashMap<&String, &String>,
+    ref_id: &str,
+    mesh_name: &str,
+) -> TES3Object {
+    let damage = |prop_name: &str| {
+        get_prop(prop_name, entity_props)
+            .parse::<u8>()
+            .unwrap_or_default()
+    };
+
+    TES3Object::Weapon(Weapon {
+        flags: ObjectFlags::default(),
+        id: ref_id.to_owned(),
+        name: get_prop("Name", entity_props),
+        script: get_prop("Script", entity_props),
+        mesh: mesh_name.to_owned(),
+        icon: get_prop("Icon", entity_props),
+        enchanting: get_prop("Enchantment", entity_props),
+        data: WeaponData {
+            weight: get_prop("Weight", entity_props)
+                .parse::<f32>()
+                .unwrap_or_default(),
+            value: get_prop("Value", entity_props)
+                .parse::<u32>()
+                .unwrap_or_default(),
+            weapon_type: get_prop("WeaponType", entity_props)
+                .parse::<u16>()
+                .unwrap_or_default()
+                .try_into()
+                .expect("Invalid Weapon Type!"),
+            health: get_prop("Health", entity_props)
+                .parse::<u16>()
+                .unwrap_or_default(),
+            speed: get_prop("Speed", entity_props)
+                .parse::<f32>()
+                .unwrap_or(1.0),
+            reach: get_prop("Reach", entity_props)
+                .parse::<f32>()
+                .unwrap_or(1.0),
+            enchantment: get_prop("EnchantmentPoints", entity_props)
+                .parse::<u16>()
+                .unwrap_or_default(),
+            chop_min: damage("ChopMin"),
+            chop_max: damage("ChopMax"),
+            slash_min: damage("SlashMin"),
+            slash_max: damage("SlashMax"),
+            thrust_min: damage("ThrustMin"),
+            thrust_max: damage("ThrustMax"),
+            flags: WeaponFlags::from_bits(
+                get_prop("WeaponFlags", entity_props)
+                    .parse::<u32>()
+                    .unwrap_or_default(),
+            )
+            .expect("Invalid Weapon Flags!"),
+        },
+    })
+}
+
 fn collect_effects(prop_map: &HashMap<&String, &String>, effects_size: u8) -> Vec<Effect> {
     let mut effects: Vec<Effect> = vec![];
 
@@ -501,31 +972,104 @@ fn collect_effects(prop_map: &HashMap<&String, &String>, effects_size: u8) -> Ve
     effects
 }
 
-fn collect_biped_objects(prop_map: &HashMap<&String, &String>) -> Vec<BipedObject> {
-    let mut biped_objects = Vec::new();
+/// Body part type for each biped slot, in slot order. Shields and weapons have no part of their own.
+const BIPED_SLOT_PARTS: [Option<u8>; 27] = [
+    Some(0),  // Head
+    Some(1),  // Hair
+    Some(2),  // Neck
+    Some(3),  // Chest
+    Some(4),  // Groin
+    Some(4),  // Skirt
+    Some(5),  // Right Hand
+    Some(5),  // Left Hand
+    Some(6),  // Right Wrist
+    Some(6),  // Left Wrist
+    None,     // Shield
+    Some(7),  // Right Forearm
+    Some(7),  // Left Forearm
+    Some(8),  // Right Upper Arm
+    Some(8),  // Left Upper Arm
+    Some(9),  // Right Foot
+    Some(9),  // Left Foot
+    Some(10), // Right Ankle
+    Some(10), // Left Ankle
+    Some(11), // Right Knee
+    Some(11), // Left Knee
+    Some(12), // Right Upper Leg
+    Some(12), // Left Upper Leg
+    Some(13), // Right Pauldron
+    Some(13), // Left Pauldron
+    None,     // Weapon
+    Some(14), // Tail
+];
 
-    for count in 1..7 {
-        match prop_map.get(&format!("SlotType{count}")) {
-            Some(biped_object) => biped_objects.push(BipedObject {
-                biped_object_type: biped_object
-                    .parse::<u8>()
-                    .unwrap_or_default()
-                    .try_into()
-                    .expect("Invalid Biped Object Type!"),
-                male_bodypart: prop_map
-                    .get(&format!("male_part{count}"))
-                    .unwrap_or(&&String::default())
-                    .to_string(),
-                female_bodypart: prop_map
-                    .get(&format!("female_part{count}"))
-                    .unwrap_or(&&String::default())
-                    .to_string(),
-            }),
-            None => continue,
+/// Biped slots each armor type may cover, in armor type order
+const ARMOR_TYPE_SLOTS: [&[u8]; 11] = [
+    &[0, 1],                   // Helmet
+    &[2, 3],                   // Cuirass
+    &[14, 24],                 // Left Pauldron
+    &[13, 23],                 // Right Pauldron
+    &[4, 5, 19, 20, 21, 22],   // Greaves
+    &[15, 16, 17, 18, 19, 20], // Boots
+    &[7, 9, 12],               // Left Gauntlet
+    &[6, 8, 11],               // Right Gauntlet
+    &[10],                     // Shield
+    &[7, 9, 12],               // Left Bracer
+    &[6, 8, 11],               // Right Bracer
+];
+
+fn validate_armor_slots(armor_type: u32, slots: &[(u8, u8)], ref_id: &str) {
+    let allowed_slots = ARMOR_TYPE_SLOTS
+        .get(armor_type as usize)
+        .expect("Invalid Armor Type!");
+
+    for (count, slot) in slots {
+        if !allowed_slots.contains(slot) {
+            println!(
+                "Warning! Slot {count} of {ref_id} uses biped slot {slot}, which armor type {armor_type} does not normally cover."
+            );
         }
     }
+}
+
+/// Slot number and biped slot type of every slot in use
+fn collect_biped_slots(prop_map: &HashMap<&String, &String>) -> Vec<(u8, u8)> {
+    (1..=8)
+        .filter_map(|count| {
+            prop_map
+                .get(&format!("SlotType{count}"))
+                .map(|biped_object| (count, biped_object.parse::<u8>().unwrap_or_default()))
+        })
+        .collect()
+}
+
+/// Uses the part named for this slot, or names one after the object if only a mesh was given
+fn biped_part_id(
+    prop_map: &HashMap<&String, &String>,
+    ref_id: &str,
+    count: u8,
+    sex: &str,
+) -> String {
+    match get_prop(&format!("{sex}_part{count}"), prop_map) {
+        part if part.is_empty()
+            && !get_prop(&format!("{sex}_mesh{count}"), prop_map).is_empty() =>
+        {
+            let part = format!("{ref_id}_{count}_{}", &sex[..1]);
+            part[..part.len().min(32)].to_string()
+        }
+        part => part,
+    }
+}
 
-    biped_objects
+fn collect_biped_objects(prop_map: &HashMap<&String, &String>, ref_id: &str) -> Vec<BipedObject> {
+    collect_biped_slots(prop_map)
+        .into_iter()
+        .map(|(count, slot)| BipedObject {
+            biped_object_type: slot.try_into().expect("Invalid Biped Object Type!"),
+            male_bodypart: biped_part_id(prop_map, ref_id, count, "male"),
+            female_bodypart: biped_part_id(prop_map, ref_id, count, "female"),
+        })
+        .collect()
 }
 
 fn collect_contained_objects(
@@ -551,6 +1095,28 @@ fn collect_contained_objects(
     contained_objects
 }
 
+fn collect_spells(prop_map: &HashMap<&String, &String>) -> Vec<String> {
+    (1..=8)
+        .map(|count| get_prop(&format!("Spell{count}_Id"), prop_map))
+        .filter(|spell| !spell.is_empty())
+        .collect()
+}
+
+fn collect_ai_data(prop_map: &HashMap<&String, &String>) -> AiData {
+    let setting = |prop_name: &str, default: u8| match get_prop(prop_name, prop_map).parse::<u8>() {
+        Ok(value) => value,
+        Err(_) => default,
+    };
+
+    AiData {
+        hello: setting("Hello", 30),
+        fight: setting("Fight", 30),
+        flee: setting("Flee", 30),
+        alarm: setting("Alarm", 0),
+        ..Default::default()
+    }
+}
+
 fn collect_list_creatures(prop_map: &HashMap<&String, &String>) -> Vec<(String, u16)> {
     let mut contained_objects = Vec::new();
 
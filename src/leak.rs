@@ -0,0 +1,254 @@
+use shambler::Vector3 as SV3;
+use std::{collections::VecDeque, fmt::Write, fs, path::Path};
+
+use crate::{get_prop, is_world_class, parse_origin, MapData};
+
+/// Smallest gap which can be found, in map units. Only maps small enough to fit in MAX_CELLS
+/// at this size get it; larger ones use bigger cells and say so.
+const MIN_CELL_SIZE: f32 = 8.0;
+
+/// Larger maps are checked on a coarser grid to keep memory use down
+const MAX_CELLS: usize = 1 << 23;
+
+const UNVISITED: u32 = u32::MAX;
+
+/// Flood fills the empty space around `info_player_start`, as Quake compilers do. If the fill
+/// reaches past the worldspawn brushes, the void is visible from inside the map, and the path
+/// it took is written beside the map as a trenchbroom point file.
+pub fn check(map_name: &str, map_data: &MapData) {
+    let Some(start) = map_data
+        .point_entities()
+        .into_iter()
+        .map(|entity_id| map_data.get_entity_properties(entity_id))
+        .find(|prop_map| get_prop("classname", prop_map) == "info_player_start")
+        .map(|prop_map| parse_origin(&get_prop("origin", &prop_map)))
+    else {
+        return println!("Skipping leak check, since the map has no info_player_start.");
+    };
+
+    let Some(grid) = Grid::new(map_data) else {
+        return;
+    };
+
+    let start_index = grid.index_of(start);
+    if grid.solid[start_index] {
+        return println!(
+            "Warning! info_player_start is inside a brush, so the map can't be checked for leaks."
+        );
+    }
+
+    let pts_path = Path::new(map_name).with_extension("pts");
+    // An old point file would point at a leak which may have been fixed
+    let _ = fs::remove_file(&pts_path);
+
+    let mut parents = vec![UNVISITED; grid.solid.len()];
+    let mut queue = VecDeque::from([start_index]);
+    parents[start_index] = start_index as u32;
+
+    while let Some(index) = queue.pop_front() {
+        let cell = grid.cell_of(index);
+
+        // The grid has a margin of empty cells around every brush, so reaching it means the fill got out
+        if grid.is_edge(cell) {
+            let path = grid.trace(&parents, index);
+            write_point_file(&pts_path, &path);
+            println!(
+                "Warning! Map leaks near {:?}. Load {} in trenchbroom with File > Load Point File to see where.",
+                path.first().map(|point| [point.x, point.y, point.z]).unwrap_or_default(),
+                pts_path.display()
+            );
+            return;
+        }
+
+        for neighbour in grid.neighbours(cell) {
+            if parents[neighbour] == UNVISITED && !grid.solid[neighbour] {
+                parents[neighbour] = index as u32;
+                queue.push_back(neighbour);
+            }
+        }
+    }
+}
+
+struct Grid {
+    origin: SV3,
+    cell_size: f32,
+    size: [usize; 3],
+    solid: Vec<bool>,
+}
+
+impl Grid {
+    /// Cells are solid when they touch a sealing brush, so walls thinner than a cell still seal
+    fn new(map_data: &MapData) -> Option<Grid> {
+        let brushes: Vec<_> = map_data
+            .brush_entities()
+            .into_iter()
+            .filter(|(entity_id, _)| {
+                let classname = get_prop("classname", &map_data.get_entity_properties(entity_id));
+                is_world_class(&classname)
+            })
+            .flat_map(|(_, brushes)| brushes)
+            .filter(|brush_id| !map_data.brush_is_origin(brush_id))
+            .collect();
+
+        if brushes.is_empty() {
+            return None;
+        }
+
+        let (mins, maxs) = brushes
+            .iter()
+            .map(|brush_id| map_data.brush_bounds(&[**brush_id]))
+            .fold(
+                (SV3::repeat(f32::MAX), SV3::repeat(f32::MIN)),
+                |(mins, maxs), (brush_mins, brush_maxs)| {
+                    (mins.inf(&brush_mins), maxs.sup(&brush_maxs))
+                },
+            );
+
+        let extent = maxs - mins;
+        let volume = extent.x * extent.y * extent.z;
+        let cell_size = MIN_CELL_SIZE.max((volume / MAX_CELLS as f32).cbrt().ceil());
+
+        // Gaps smaller than a cell are sealed by the brushes either side of them
+        if cell_size > MIN_CELL_SIZE {
+            println!(
+                "Warning! The map is too large to check for leaks on a {MIN_CELL_SIZE} unit grid, so a {cell_size} unit grid was used. Gaps narrower than that won't be found."
+            );
+        }
+
+        // One empty cell on every side, for the fill to escape into
+        let origin = mins - SV3::repeat(cell_size * 1.5);
+        let size = extent.map(|axis| (axis / cell_size).ceil() as usize + 3);
+        let size = [size.x, size.y, size.z];
+
+        let mut grid = Grid {
+            origin,
+            cell_size,
+            size,
+            solid: vec![false; size[0] * size[1] * size[2]],
+        };
+
+        for brush_id in brushes {
+            let planes = map_data.brush_planes(brush_id);
+            let (brush_mins, brush_maxs) = map_data.brush_bounds(&[*brush_id]);
+            let first = grid.cell_at(brush_mins);
+            let last = grid.cell_at(brush_maxs);
+
+            for x in first[0]..=last[0] {
+                for y in first[1]..=last[1] {
+                    for z in first[2]..=last[2] {
+                        let center = grid.center([x, y, z]);
+                        let half = cell_size / 2.0;
+
+                        // The cell is clear of the brush only if one face has the whole cell in front of it
+                        let touches = planes.iter().all(|(vertex, normal)| {
+                            let reach = half * (normal.x.abs() + normal.y.abs() + normal.z.abs());
+                            (center - vertex).dot(normal) - reach <= 0.0
+                        });
+
+                        if touches {
+                            let index = grid.index([x, y, z]);
+                            grid.solid[index] = true;
+                        }
+                    }
+                }
+            }
+        }
+
+        Some(grid)
+    }
+
+    fn cell_at(&self, point: SV3) -> [usize; 3] {
+        let cell = (point - self.origin) / self.cell_size;
+        [
+            (cell.x.max(0.0) as usize).min(self.size[0] - 1),
+            (cell.y.max(0.0) as usize).min(self.size[1] - 1),
+            (cell.z.max(0.0) as usize).min(self.size[2] - 1),
+        ]
+    }
+
+    fn index_of(&self, point: SV3) -> usize {
+        self.index(self.cell_at(point))
+    }
+
+    fn index(&self, cell: [usize; 3]) -> usize {
+        (cell[2] * self.size[1] + cell[1]) * self.size[0] + cell[0]
+    }
+
+    fn cell_of(&self, index: usize) -> [usize; 3] {
+        [
+            index % self.size[0],
+            index / self.size[0] % self.size[1],
+            index / (self.size[0] * self.size[1]),
+        ]
+    }
+
+    fn center(&self, cell: [usize; 3]) -> SV3 {
+        self.origin
+            + SV3::new(cell[0] as f32, cell[1] as f32, cell[2] as f32) * self.cell_size
+            + SV3::repeat(self.cell_size / 2.0)
+    }
+
+    fn is_edge(&self, cell: [usize; 3]) -> bool {
+        (0..3).any(|axis| cell[axis] == 0 || cell[axis] == self.size[axis] - 1)
+    }
+
+    fn neighbours(&self, cell: [usize; 3]) -> Vec<usize> {
+        let mut neighbours = Vec::new();
+
+        for axis in 0..3 {
+            if cell[axis] > 0 {
+                let mut neighbour = cell;
+                neighbour[axis] -= 1;
+                neighbours.push(self.index(neighbour));
+            }
+            if cell[axis] + 1 < self.size[axis] {
+                let mut neighbour = cell;
+                neighbour[axis] += 1;
+                neighbours.push(self.index(neighbour));
+            }
+        }
+
+        neighbours
+    }
+
+    /// Cell centers from the leak back to the start, leaving out those along a straight line
+    fn trace(&self, parents: &[u32], leak: usize) -> Vec<SV3> {
+        let mut cells = vec![leak];
+        let mut index = leak;
+
+        while parents[index] as usize != index {
+            index = parents[index] as usize;
+            cells.push(index);
+        }
+
+        let points: Vec<SV3> = cells
+            .into_iter()
+            .map(|index| self.center(self.cell_of(index)))
+            .collect();
+
+        let mut path: Vec<SV3> = Vec::new();
+        for (index, point) in points.iter().enumerate() {
+            let is_bend = match (index.checked_sub(1), points.get(index + 1)) {
+                (Some(previous), Some(next)) => {
+                    (point - points[previous]).normalize() != (next - point).normalize()
+                }
+                _ => true,
+            };
+
+            if is_bend {
+                path.push(*point);
+            }
+        }
+
+        path
+    }
+}
+
+fn write_point_file(path: &Path, points: &[SV3]) {
+    let mut contents = String::new();
+    for point in points {
+        let _ = writeln!(contents, "{} {} {}", point.x, point.y, point.z);
+    }
+
+    fs::write(path, contents).expect(&format!("Saving {} failed!", path.display()));
+}
@@ -1,22 +1,44 @@
 use std::{
     cmp::min,
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap},
     fs,
     path::Path,
 };
 
-use clap::{Arg, Command};
-use shambler::Vector3 as SV3;
+use clap::{Arg, ArgAction, Command};
+use shambler::{entity::EntityId, Vector3 as SV3};
 use tes3::esp::{self, Cell, EditorId, Header, Plugin, Static, TES3Object};
 
 mod brush_ni_node;
 use brush_ni_node::BrushNiNode;
+mod decompile;
+mod delete_volume;
+mod fgd;
 mod map_data;
 use map_data::MapData;
+mod master_cell;
+use master_cell::MasterCell;
+mod merge;
+use merge::MergeBase;
 mod mesh;
 use mesh::Mesh;
 mod game_object;
+mod leak;
+mod manifest;
+use manifest::Manifest;
+mod model_export;
+mod mover;
+use mover::Mover;
+mod path_grid;
+mod prefab;
+use prefab::Prefabs;
+mod ref_numbers;
+use ref_numbers::RefNumbers;
+mod scatter;
+mod scripts;
 mod surfaces;
+mod trigger;
+use trigger::Trigger;
 
 #[global_allocator]
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
@@ -49,11 +71,110 @@ fn main() {
             .help("Whether to compile in openmw, morrowind.exe, or librequake mode.")
             .long("mode")
             .value_parser(validate_mode),
+        Arg::new("PRUNE_REFS")
+            .help("Free the reference numbers of entities which are no longer in the map. They are otherwise kept so that deleting an entity never renumbers another one.")
+            .long("prune-refs")
+            .action(ArgAction::SetTrue),
+        Arg::new("STRICT")
+            .help("Stop with an error on entity classes morrobroom doesn't know, instead of leaving them out of the plugin.")
+            .long("strict")
+            .action(ArgAction::SetTrue),
+        Arg::new("NO_LEAK_CHECK")
+            .help("Skip checking the map for leaks, which also leaves any point file from an earlier check in place.")
+            .long("no-leak-check")
+            .action(ArgAction::SetTrue),
+        Arg::new("SKIP_HIDDEN_LAYERS")
+            .help("Leave trenchbroom layers which are hidden out of the plugin, as well as those omitted from export.")
+            .long("skip-hidden-layers")
+            .action(ArgAction::SetTrue),
+        Arg::new("MANIFEST")
+            .help("Write a hash of every file the compile produces to this path, for checking that two builds of the same map are identical.")
+            .long("manifest"),
+        Arg::new("VERIFY")
+            .help("Compare the build against the existing manifest instead of overwriting it, exiting with an error if any file differs.")
+            .long("verify")
+            .requires("MANIFEST")
+            .action(ArgAction::SetTrue),
     ])
+    .subcommand(
+        Command::new("decompile")
+            .about("Convert an interior cell from an existing plugin back into a trenchbroom .map file.")
+            .arg_required_else_help(true)
+            .args(&[
+                Arg::new("PLUGIN_PATH")
+                    .help("Plugin containing the cell, such as Morrowind.esm.")
+                    .long("plugin")
+                    .value_parser(validate_existing_plugin)
+                    .required(true),
+                Arg::new("CELL_NAME")
+                    .help("Name of the interior cell to decompile.")
+                    .long("cell")
+                    .required(true),
+                Arg::new("MAP_NAME")
+                    .help("Output map file name.")
+                    .long("out")
+                    .value_parser(validate_output_map)
+                    .required(true),
+                Arg::new("SCALE")
+                    .help("Scale the map will be compiled with. Reference positions are divided by it.")
+                    .long("scale")
+                    .value_parser(validate_scale),
+            ]),
+    )
+    .subcommand(
+        Command::new("export-models")
+            .about("Convert Morrowind .nif models into .obj files which trenchbroom can preview.")
+            .arg_required_else_help(true)
+            .args(&[
+                Arg::new("PLUGIN_PATH")
+                    .help("Plugin whose base objects should be exported, named by their ref ids. May be repeated.")
+                    .long("plugin")
+                    .value_parser(validate_existing_plugin)
+                    .action(ArgAction::Append),
+                Arg::new("MESH_PATH")
+                    .help("Individual mesh to export, relative to the Meshes folder. May be repeated.")
+                    .long("mesh")
+                    .action(ArgAction::Append),
+                Arg::new("OUT_DIR")
+                    .help("Folder to write models into. Should be the morrobroom folder inside the trenchbroom game path for placements to find them.")
+                    .long("out")
+                    .required(true),
+                Arg::new("SCALE")
+                    .help("Scale the map will be compiled with. Models are shrunk by it to match map units.")
+                    .long("scale")
+                    .value_parser(validate_scale),
+            ]),
+    )
+    .subcommand(
+        Command::new("fgd")
+            .about("Generate the trenchbroom entity definition file from the classes morrobroom understands.")
+            .arg_required_else_help(true)
+            .arg(
+                Arg::new("FGD_PATH")
+                    .help("Output .fgd file name.")
+                    .long("out")
+                    .required(true),
+            ),
+    )
+    .args_conflicts_with_subcommands(true)
+    .subcommand_negates_reqs(true)
     .get_matches();
 
+    match args.subcommand() {
+        Some(("decompile", sub_args)) => return decompile::decompile(sub_args),
+        Some(("export-models", sub_args)) => return model_export::export_models(sub_args),
+        Some(("fgd", sub_args)) => {
+            let fgd_path = sub_args.get_one::<String>("FGD_PATH").unwrap();
+            fs::write(fgd_path, fgd::render(&fgd::classes()))
+                .expect(&format!("Saving {fgd_path} failed!"));
+            return println!("Wrote {fgd_path} to disk successfully.");
+        }
+        _ => {}
+    }
+
     let map_name = args.get_one::<String>("MAP_NAME").unwrap();
     let scale_mode = args.get_one::<f32>("SCALE").unwrap_or(&1.0);
+    let strict = args.get_flag("STRICT");
 
     let (workdir, map_dir, plugin_name) = match args.get_one::<String>("PLUGIN_PATH") {
         Some(name) => {
@@ -73,87 +194,83 @@ fn main() {
     // It can't be done multiple times :/
     let mut cell = None;
     let mut created_objects = Vec::new();
-    let mut processed_base_objects: HashSet<String> = HashSet::new();
-
-    let map_data = MapData::new(map_name);
-
-    let mut used_indices: HashSet<u32> = plugin
-        .objects_of_type::<Cell>()
-        .flat_map(|cell| {
-            cell.references.iter().filter_map(
-                |((mast_idx, ref_idx), _reference)| {
-                    if *mast_idx == 0 {
-                        Some(*ref_idx)
-                    } else {
-                        None
-                    }
-                },
-            )
-        })
-        .collect();
+    let mut processed_base_objects: BTreeSet<String> = BTreeSet::new();
+    let mut trigger_scripts: HashMap<String, (String, EntityId)> = HashMap::new();
+
+    for script in scripts::from_folder(map_name) {
+        processed_base_objects.insert(script.editor_id().to_string());
+        created_objects.push(script);
+    }
+
+    let mut map_data = MapData::new(map_name);
+    for layer_name in map_data.exclude_layers(args.get_flag("SKIP_HIDDEN_LAYERS")) {
+        println!("Leaving layer {layer_name} out of the plugin.");
+    }
+    let entity_classes = fgd::classes();
+    let mut manifest = Manifest::new(&workdir);
+    let mut prefabs = Prefabs::new(&workdir, &map_dir, scale_mode);
+
+    // References placed by hand in the cell this map rebuilds survive the merge, so they keep their numbers too
+    let rebuilt_cell = map_data
+        .brush_entities()
+        .into_iter()
+        .map(|(entity_id, _)| map_data.get_entity_properties(entity_id))
+        .find(|prop_map| get_prop("classname", prop_map) == "worldspawn")
+        .map(|prop_map| get_prop("Name", &prop_map))
+        .filter(|name| !name.is_empty())
+        .unwrap_or(map_dir.clone());
+
+    let merge_base = MergeBase::load(&plugin_name, &plugin, &map_dir);
+    let master_cell = MasterCell::load(&map_data, scale_mode);
+    let master_index = master_cell.as_ref().map(|master_cell| {
+        create_header_if_missing(&mut plugin);
+        master_cell.add_to_header(&mut plugin)
+    });
+    // Maps added to a master cell open onto rooms they can't see, so they can't be sealed
+    if master_cell.is_none() && !args.get_flag("NO_LEAK_CHECK") {
+        leak::check(map_name, &map_data);
+    }
+
+    let cell_offset = master_cell
+        .as_ref()
+        .map_or(SV3::zeros(), |master_cell| master_cell.offset);
+
+    let mut ref_numbers = RefNumbers::load(
+        map_name,
+        &map_data,
+        plugin
+            .objects_of_type::<Cell>()
+            .flat_map(|cell| match cell.name.eq_ignore_ascii_case(&rebuilt_cell) {
+                true => merge_base.hand_placed_references(cell),
+                false => cell
+                    .references
+                    .keys()
+                    .filter(|(mast_idx, _)| *mast_idx == 0)
+                    .map(|(_, ref_idx)| *ref_idx)
+                    .collect(),
+            })
+            .collect(),
+    );
 
     assert!(
         map_data.geomap.entity_brushes.len() > 0,
         "No brushes found in map! You probably used an apostrophe in worldspawn properties."
     );
 
-    for (entity_id, brushes) in map_data.geomap.entity_brushes.iter() {
+    for (entity_id, brushes) in map_data.brush_entities() {
         let prop_map = map_data.get_entity_properties(entity_id);
+        warn_unknown_properties(&entity_classes, &prop_map, entity_id);
 
-        let mut mesh = Mesh::from_map(brushes, &map_data, &scale_mode, entity_id);
-
-        match prop_map.get(&"_tb_id".to_string()) {
-            Some(group_id) => {
-                // This object is a group
-                let mut ref_instances = 0;
-                let mut nodes = Vec::new();
-                let mut processed_group_objects: Vec<String> = Vec::new();
-
-                for (entity_id, brushes) in map_data.geomap.entity_brushes.iter() {
-                    let prop_map = map_data.get_entity_properties(entity_id);
-                    // let group_id;
-
-                    match prop_map.get(&"_tb_id".to_string()) {
-                        Some(_) => continue,
-                        None => {}
-                    }
-
-                    // We also should account for linked groups in the case below!
-                    match prop_map.get(&"_tb_group".to_string()) {
-                        Some(obj_group) => {
-                            if obj_group != group_id {
-                                // println!("Found another group! Bailing on creating this mesh and saving it into the cellref.");
-                                continue;
-                            };
-                        }
-                        None => {
-                            // println!("This object isn't part of a group, don't do anything with it here.");
-                            continue;
-                        }
-                    }
-
-                    match prop_map.get(&"RefId".to_string()) {
-                        Some(ref_id) => {
-                            ref_instances += 1;
-                            if processed_group_objects.contains(ref_id) {
-                                println!("We don't have full refId support yet, but this object {ref_id} has appeared in this group {ref_instances} times"); // In theory by this point, we should have a mesh for this object already.
-                                                                                                                                                             // Alternatively, we have to generate it here, which is probably going to be likely.
-                                continue; // If it does exist, though, we need to simply derive its placement
-                            }
-                            println!("Adding {ref_id} to unique group set. This should actually not be generated as part of the mesh, but rather create a new one for this unique object. Then it should be placed in the ESP file and referred to later.");
-                            processed_group_objects.push(ref_id.to_string());
-                        }
-                        None => {} // object has no refid, and it's not a group, but it is a member of a group. This maybe shouldn't happen
-                    }
+        // Merged into the worldspawn mesh, so there is nothing to build for them here
+        if merges_into_worldspawn(&get_prop("classname", &prop_map)) {
+            continue;
+        }
 
-                    nodes.extend(BrushNiNode::from_brushes(brushes, &map_data, entity_id));
-                }
+        let mut mesh = Mesh::from_map(brushes, &map_data, &scale_mode, entity_id);
 
-                for node in nodes {
-                    mesh.attach_node(node);
-                }
-            }
-            None => {}
+        // An explicit origin key takes priority over any origin brush in the entity
+        if let Some(origin) = prop_map.get(&"origin".to_string()) {
+            mesh.pivot = Some(parse_origin(origin));
         }
 
         let ref_id = match prop_map.get(&"RefId".to_string()) {
@@ -193,10 +310,29 @@ fn main() {
                 }
                 "item_Armor" => {
                     mesh.game_object = game_object::armor(&prop_map, &ref_id, &mesh_name);
+
+                    for body_part in
+                        game_object::body_parts(&prop_map, &ref_id, esp::BodyPartType::Armor)
+                    {
+                        if processed_base_objects.insert(body_part.editor_id().to_string()) {
+                            created_objects.push(body_part);
+                        }
+                    }
                 }
                 "item_Book" => {
                     mesh.game_object = game_object::book(&prop_map, &ref_id, &mesh_name);
                 }
+                "item_Clothing" => {
+                    mesh.game_object = game_object::clothing(&prop_map, &ref_id, &mesh_name);
+
+                    for body_part in
+                        game_object::body_parts(&prop_map, &ref_id, esp::BodyPartType::Clothing)
+                    {
+                        if processed_base_objects.insert(body_part.editor_id().to_string()) {
+                            created_objects.push(body_part);
+                        }
+                    }
+                }
                 "item_Ingredient" => {
                     mesh.game_object = game_object::ingredient(&prop_map, &ref_id, &mesh_name);
                 }
@@ -205,11 +341,95 @@ fn main() {
                     mesh.game_object =
                         game_object::light(&prop_map, scale_mode, &ref_id, &mesh_name);
                 }
+                "item_Lockpick" => {
+                    mesh.game_object = game_object::lockpick(&prop_map, &ref_id, &mesh_name);
+                }
                 "item_Misc" => {
                     mesh.game_object = game_object::misc(&prop_map, &ref_id, &mesh_name);
                 }
+                "item_Probe" => {
+                    mesh.game_object = game_object::probe(&prop_map, &ref_id, &mesh_name);
+                }
+                "item_RepairItem" => {
+                    mesh.game_object = game_object::repair_item(&prop_map, &ref_id, &mesh_name);
+                }
+                "item_Weapon" => {
+                    mesh.game_object = game_object::weapon(&prop_map, &ref_id, &mesh_name);
+                }
+                "func_rotating" | "func_door_rotating" => {
+                    let mover = Mover::from_props(classname, &prop_map)
+                        .expect("Mover classes are always matched here");
+
+                    mesh.attach_mover(&mover);
+                    mesh.game_object = game_object::activator(&prop_map, &ref_id, &mesh_name);
+
+                    // Only generate a script if the mapper didn't provide their own
+                    if get_prop("Script", &prop_map).is_empty()
+                        && get_prop("ScriptFile", &prop_map).is_empty()
+                    {
+                        let script_id = format!("{ref_id}_sc");
+                        let script_id = script_id[..min(script_id.len(), 32)].to_string();
+
+                        if !processed_base_objects.contains(&script_id) {
+                            created_objects.push(scripts::compile(
+                                &script_id,
+                                &mover.script(&script_id, &prop_map),
+                            ));
+                            processed_base_objects.insert(script_id.clone());
+                        }
+
+                        if let TES3Object::Activator(ref mut activator) = mesh.game_object {
+                            activator.script = script_id;
+                        }
+                    }
+                }
+                "trigger_Script" | "trigger_Hurt" => {
+                    let mut trigger =
+                        Trigger::from_props(classname, map_data.brush_bounds(brushes), scale_mode)
+                            .expect("Trigger classes are always matched here");
+                    // Scripts compare against GetPos, so the bounds move with the references
+                    trigger.mins += cell_offset;
+                    trigger.maxs += cell_offset;
+
+                    // Triggers are never seen or collided with, so only the reference position is kept
+                    let mut marker = Mesh::marker(scale_mode);
+                    marker.pivot = Some(mesh.origin());
+                    mesh = marker;
+
+                    let script_id = trigger.script_id(&ref_id, &prop_map);
+
+                    // The activator is shared by RefId, so it can only carry one trigger's script
+                    match trigger_scripts.get(&ref_id) {
+                        Some((other_script, other_entity)) if *other_script != script_id => panic!(
+                            "Triggers {other_entity} and {entity_id} share the id {ref_id} but differ in bounds or props! \
+                             Give each its own RefId, different within the first 32 characters."
+                        ),
+                        Some(_) => {}
+                        None => {
+                            trigger_scripts.insert(ref_id.clone(), (script_id.clone(), entity_id));
+                        }
+                    }
+
+                    if processed_base_objects.insert(script_id.clone()) {
+                        created_objects.push(scripts::compile(
+                            &script_id,
+                            &trigger.script(&script_id, &prop_map),
+                        ));
+                    }
+
+                    mesh.game_object = TES3Object::Activator(esp::Activator {
+                        id: ref_id.to_owned(),
+                        mesh: mesh_name.to_owned(),
+                        script: script_id,
+                        ..Default::default()
+                    });
+                }
+                "world_DeleteVolume" => continue, // Applied to the master cell once everything is placed
                 "worldspawn" => {
-                    let mut local_cell = game_object::cell(&prop_map);
+                    let mut local_cell = match &master_cell {
+                        Some(master_cell) => master_cell.cell.clone(),
+                        None => game_object::cell(&prop_map),
+                    };
                     if local_cell.name.is_empty() {
                         local_cell.name = map_dir.clone();
                     }
@@ -222,8 +442,41 @@ fn main() {
                         mesh: mesh_name.to_owned(),
                         flags: esp::ObjectFlags::default(),
                     });
+
+                    // Grouping and detail brushes have no identity in game, so they become part of the scene
+                    for (scene_entity_id, scene_brushes) in map_data.brush_entities() {
+                        let scene_props = map_data.get_entity_properties(scene_entity_id);
+                        if merges_into_worldspawn(&get_prop("classname", &scene_props)) {
+                            for node in
+                                BrushNiNode::from_brushes(scene_brushes, &map_data, scene_entity_id)
+                            {
+                                mesh.attach_node(node);
+                            }
+                        }
+                    }
                 }
-                "world_Detail" => {
+                "func_scatter" => {
+                    if let Some(local_cell) = &mut cell {
+                        let placements =
+                            scatter::scatter(&map_data, entity_id, brushes, scale_mode);
+                        println!("Scattering {} objects for {entity_id}", placements.len());
+
+                        for scattered in placements {
+                            let refr_index =
+                                ref_numbers.next_part_index(entity_id, &scattered.key, local_cell);
+                            insert_reference(
+                                local_cell,
+                                refr_index,
+                                scattered.ref_id,
+                                scattered.position,
+                                scattered.rotation,
+                            );
+                            scale_reference(local_cell, refr_index, scattered.scale);
+                        }
+                    }
+                    continue; // The brush only marks where to scatter
+                }
+                "world_Detail" | "func_wall" | "func_illusionary" => {
                     processed_base_objects.insert(ref_id.clone());
                     mesh.game_object = TES3Object::Static(Static {
                         id: ref_id.to_owned(),
@@ -232,6 +485,9 @@ fn main() {
                     })
                 }
                 _ => {
+                    if strict {
+                        panic!("No matching object type found! {classname} requested for {entity_id}, whose brushes would be lost.");
+                    }
                     println!(
                         "No matching object type found! {classname} requested for {entity_id}"
                     );
@@ -241,7 +497,27 @@ fn main() {
             None => {}
         }
 
-        let mesh_distance: SV3 = Mesh::centroid(&mesh.node_distances) * (*scale_mode as f32);
+        // Script files are compiled per entity, so templates can read the entity's properties
+        if let Some(script_file) = prop_map.get(&"ScriptFile".to_string()) {
+            let script_id = format!("{ref_id}_sc");
+            let script_id = script_id[..min(script_id.len(), 32)].to_string();
+            let script = scripts::from_template(
+                map_name,
+                script_file,
+                &script_id,
+                &prop_map,
+                &entity_classes,
+            );
+            let script_id = script.editor_id().to_string();
+
+            if processed_base_objects.insert(script_id.clone()) {
+                created_objects.push(script);
+            }
+
+            game_object::set_script(&mut mesh.game_object, &script_id);
+        }
+
+        let mesh_distance: SV3 = mesh.origin() * (*scale_mode as f32);
         mesh.final_distance = mesh_distance;
         mesh.mangle = match get_prop("mangle", &prop_map) {
             mangle if mangle.is_empty() => *get_rotation(&"0 0 0".to_string()),
@@ -255,21 +531,24 @@ fn main() {
             let mesh_path = format!("{workdir}/Meshes/{mesh_name}");
             println!("Saving base object definition & mesh for {ref_id} to plugin as {mesh_path}");
             mesh.save(&mesh_path);
+            manifest.record(&mesh_path);
             created_objects.push(mesh.game_object.clone());
         }
 
-        append_cell_reference(
-            &mut used_indices,
+        let refr_index = append_cell_reference(
+            &mut ref_numbers,
             &mut cell,
+            entity_id,
             ref_id,
             mesh_distance,
             mesh.mangle,
         );
+        apply_reference_properties(&mut cell, refr_index, &prop_map, entity_id);
     }
 
-    for entity_id in map_data.geomap.point_entities.iter() {
+    for entity_id in map_data.point_entities() {
         let prop_map = map_data.get_entity_properties(entity_id);
-        let lowest_available_index = lowest_available_index(&used_indices);
+        warn_unknown_properties(&entity_classes, &prop_map, entity_id);
 
         match prop_map
             .get(&"classname".to_string())
@@ -277,8 +556,12 @@ fn main() {
             .as_str()
         {
             light if light.contains("Light_Point") => {
-                let mut ref_id = format!("{map_dir}-PL-{lowest_available_index}");
-                ref_id = ref_id[..min(ref_id.len(), 32)].to_string();
+                // Without a RefId the light is named after its place in the map, which moves as entities come and go
+                let ref_id = match prop_map.get(&"RefId".to_string()) {
+                    Some(ref_id) => ref_id.to_string(),
+                    None => format!("{map_dir}-PL-{entity_id}"),
+                };
+                let ref_id = ref_id[..min(ref_id.len(), 32)].to_string();
 
                 let radius: u32 = light
                     .chars()
@@ -290,21 +573,112 @@ fn main() {
                         "All point light types should have a radius encoded in their classnames!",
                     );
 
-                created_objects.push(game_object::point_light(
-                    &prop_map,
-                    scale_mode,
-                    radius,
-                    ref_id.as_str(),
-                ));
+                if processed_base_objects.insert(ref_id.clone()) {
+                    created_objects.push(game_object::point_light(
+                        &prop_map,
+                        scale_mode,
+                        radius,
+                        ref_id.as_str(),
+                    ));
+                }
 
                 append_cell_reference(
-                    &mut used_indices,
+                    &mut ref_numbers,
                     &mut cell,
+                    entity_id,
                     ref_id,
                     point_entity_position(scale_mode, &prop_map),
                     [0.0, 0.0, 0.0],
                 );
             }
+            classname @ ("def_Enchantment" | "def_Spell") => {
+                let ref_id = match prop_map.get(&"RefId".to_string()) {
+                    Some(ref_id) => ref_id[..min(ref_id.len(), 32)].to_string(),
+                    None => panic!(
+                        "RefIds are mandatory for all point entities, failed on {classname}, entity ID: {}",
+                        entity_id
+                    ),
+                };
+
+                if processed_base_objects.insert(ref_id.to_string()) {
+                    created_objects.push(match classname {
+                        "def_Enchantment" => game_object::enchantment(&prop_map, &ref_id),
+                        _ => game_object::spell(&prop_map, &ref_id),
+                    });
+                }
+            }
+            "ambient_Sound" => {
+                let ref_id = match prop_map.get(&"RefId".to_string()) {
+                    Some(ref_id) => ref_id[..min(ref_id.len(), 32)].to_string(),
+                    None => panic!(
+                        "RefIds are mandatory for all point entities, failed on ambient sound, entity ID: {}",
+                        entity_id
+                    ),
+                };
+
+                // Sounds can't be placed on their own, so an invisible activator loops them instead
+                let activator_id = format!("{ref_id}_emitter");
+                let activator_id = activator_id[..min(activator_id.len(), 32)].to_string();
+
+                if processed_base_objects.insert(ref_id.clone()) {
+                    let script_id = format!("{ref_id}_sc");
+                    let script_id = script_id[..min(script_id.len(), 32)].to_string();
+                    let mesh_name = format!("{map_dir}/{activator_id}.nif");
+
+                    let mesh_path = format!("{workdir}/Meshes/{mesh_name}");
+                    Mesh::marker(scale_mode).save(&mesh_path);
+                    manifest.record(&mesh_path);
+
+                    created_objects.extend([
+                        game_object::sound(&prop_map, scale_mode, &ref_id),
+                        scripts::compile(
+                            &script_id,
+                            &game_object::sound_loop_script(&script_id, &ref_id),
+                        ),
+                        TES3Object::Activator(esp::Activator {
+                            id: activator_id.clone(),
+                            mesh: mesh_name,
+                            script: script_id.clone(),
+                            ..Default::default()
+                        }),
+                    ]);
+                    processed_base_objects.extend([script_id, activator_id.clone()]);
+                }
+
+                append_cell_reference(
+                    &mut ref_numbers,
+                    &mut cell,
+                    entity_id,
+                    activator_id,
+                    point_entity_position(scale_mode, &prop_map),
+                    [0.0, 0.0, 0.0],
+                );
+            }
+            classname @ ("actor_NPC" | "actor_Creature") => {
+                let ref_id = match prop_map.get(&"RefId".to_string()) {
+                    Some(ref_id) => ref_id[..min(ref_id.len(), 32)].to_string(),
+                    None => panic!(
+                        "RefIds are mandatory for all point entities, failed on {classname}, entity ID: {}",
+                        entity_id
+                    ),
+                };
+
+                if processed_base_objects.insert(ref_id.to_string()) {
+                    created_objects.push(match classname {
+                        "actor_NPC" => game_object::npc(&prop_map, &ref_id),
+                        _ => game_object::creature(&prop_map, &ref_id),
+                    });
+                }
+
+                append_cell_reference(
+                    &mut ref_numbers,
+                    &mut cell,
+                    entity_id,
+                    ref_id,
+                    point_entity_position(scale_mode, &prop_map),
+                    *get_rotation(&get_prop("mangle", &prop_map)),
+                );
+            }
             "world_CreatureList" => {
                 let ref_id = match prop_map.get(&"RefId".to_string()) {
                     Some(ref_id) => ref_id[..min(ref_id.len(), 32)].to_string(),
@@ -320,13 +694,41 @@ fn main() {
                 }
 
                 append_cell_reference(
-                    &mut used_indices,
+                    &mut ref_numbers,
                     &mut cell,
+                    entity_id,
                     ref_id,
                     point_entity_position(scale_mode, &prop_map),
                     [0.0, 0.0, 0.0],
                 );
             }
+            "world_Reference" => {
+                let ref_id = match prop_map.get(&"RefId".to_string()) {
+                    Some(ref_id) => ref_id[..min(ref_id.len(), 32)].to_string(),
+                    None => panic!(
+                        "RefIds are mandatory for all point entities, failed on reference, entity ID: {}",
+                        entity_id
+                    ),
+                };
+
+                let refr_index = append_cell_reference(
+                    &mut ref_numbers,
+                    &mut cell,
+                    entity_id,
+                    ref_id,
+                    point_entity_position(scale_mode, &prop_map),
+                    *get_rotation(&get_prop("mangle", &prop_map)),
+                );
+
+                if let (Some(local_cell), Ok(scale)) =
+                    (&mut cell, get_prop("Scale", &prop_map).parse::<f32>())
+                {
+                    if let Some(reference) = local_cell.references.get_mut(&(0, refr_index)) {
+                        reference.scale = Some(scale);
+                    }
+                }
+                apply_reference_properties(&mut cell, refr_index, &prop_map, entity_id);
+            }
             "world_ItemList" => {
                 let ref_id = match prop_map.get(&"RefId".to_string()) {
                     Some(ref_id) => ref_id[..min(ref_id.len(), 32)].to_string(),
@@ -341,73 +743,234 @@ fn main() {
                     processed_base_objects.insert(ref_id.to_string());
                 }
             }
+            "misc_prefab" => {
+                let Some(local_cell) = &mut cell else {
+                    continue;
+                };
+
+                for part in prefabs.instance(map_name, &prop_map) {
+                    let refr_index = ref_numbers.next_part_index(entity_id, &part.key, local_cell);
+                    insert_reference(
+                        local_cell,
+                        refr_index,
+                        part.ref_id,
+                        part.position,
+                        part.rotation,
+                    );
+                    scale_reference(local_cell, refr_index, part.scale);
+                }
+            }
+            "info_pathnode" => {}      // Read when the path grid is generated
+            "info_master_anchor" => {} // Read when the master cell is loaded
+            "info_player_start" => {}  // Read by the leak check
+            // Anything else the fgd offers is read elsewhere, so only unknown classes are errors
+            class
+                if entity_classes
+                    .iter()
+                    .any(|entity_class| entity_class.name == class) => {}
             class => {
+                if strict {
+                    panic!("Unidentified point entity class: {class} on entity {entity_id}");
+                }
                 println!("Unidentified point entity class: {class}")
             }
         }
     }
 
-    if let Some(cell) = cell {
-        processed_base_objects.insert(cell.editor_id().to_string());
+    for object in prefabs.created_objects {
+        processed_base_objects.insert(object.editor_id().to_string());
+        created_objects.push(object);
+    }
+    for mesh_path in &prefabs.mesh_paths {
+        manifest.record(mesh_path);
+    }
+
+    if let Some(mut cell) = cell {
+        for reference in cell.references.values_mut() {
+            let translation = SV3::from(reference.translation) + cell_offset;
+            reference.translation = [translation.x, translation.y, translation.z];
+        }
+
+        let removed = delete_volume::apply(
+            &map_data,
+            master_cell.as_ref(),
+            master_index.unwrap_or_default(),
+            &mut cell,
+            scale_mode,
+            &map_dir,
+            &mut created_objects,
+        );
+        if !removed.is_empty() {
+            println!("Removed {} references from {}:", removed.len(), cell.name);
+            for line in removed {
+                println!("\t{line}");
+            }
+        }
+
+        // The old path grid shares the cell's id, so it's replaced along with the cell.
+        // A master cell's grid covers rooms the map can't see, so it is left alone.
+        if master_cell.is_some() {
+            println!("Keeping the path grid of {} from its master.", cell.name);
+        } else if let Some(path_grid) = path_grid::generate(&map_data, &cell.name, scale_mode) {
+            created_objects.push(path_grid);
+        }
+
         created_objects.push(esp::TES3Object::Cell(cell));
     }
 
     let fail_str = format!("Saving {plugin_name} failed!");
 
-    plugin.objects.retain(|obj| {
-        !processed_base_objects.contains(&obj.editor_id().to_string())
-            && !obj
-                .editor_id()
-                .contains(&format!("{map_dir}-PL").to_string())
-    });
-    plugin.objects.extend(created_objects);
+    merge_base.save(&created_objects);
+    let conflicts = merge::merge(&mut plugin, &merge_base, created_objects);
+    for conflict in &conflicts {
+        println!("Warning! {conflict}");
+    }
     create_header_if_missing(&mut plugin);
     plugin.sort_objects();
     plugin.save_path(&plugin_name).expect(&fail_str);
+    ref_numbers.save(args.get_flag("PRUNE_REFS"));
 
     println!("Wrote {plugin_name} to disk successfully.");
+
+    if let Some(manifest_path) = args.get_one::<String>("MANIFEST") {
+        manifest.record(&plugin_name);
+
+        if !args.get_flag("VERIFY") {
+            manifest.save(manifest_path);
+            return println!("Wrote {manifest_path} to disk successfully.");
+        }
+
+        match manifest.verify(manifest_path) {
+            Ok(()) => println!("Build matches {manifest_path}."),
+            Err(differences) => {
+                for difference in differences {
+                    println!("{difference}");
+                }
+                println!("Build does not match {manifest_path}!");
+                std::process::exit(1);
+            }
+        }
+    }
 }
 
 fn point_entity_position(scale_mode: &f32, prop_map: &HashMap<&String, &String>) -> SV3 {
-    let coords: Vec<f32> = prop_map
-        .get(&"origin".to_string())
-        .expect("All point entities must have an origin")
+    parse_origin(
+        prop_map
+            .get(&"origin".to_string())
+            .expect("All point entities must have an origin"),
+    ) * (*scale_mode)
+}
+
+fn parse_origin(origin: &str) -> SV3 {
+    let coords: Vec<f32> = origin
         .split_whitespace()
         .map(|s| s.parse::<f32>().expect("Invalid coordinate"))
         .collect();
 
     assert_eq!(coords.len(), 3, "Origin must have exactly 3 coordinates");
 
-    SV3::new(coords[0], coords[1], coords[2]) * (*scale_mode)
-}
-
-fn lowest_available_index(used_indices: &HashSet<u32>) -> u32 {
-    (1..).find(|&n| !used_indices.contains(&n)).unwrap_or(1)
+    SV3::new(coords[0], coords[1], coords[2])
 }
 
 fn append_cell_reference(
-    used_indices: &mut HashSet<u32>,
+    ref_numbers: &mut RefNumbers,
     cell: &mut Option<Cell>,
+    entity_id: &EntityId,
+    ref_id: String,
+    translation: SV3,
+    rotation: [f32; 3],
+) -> u32 {
+    let Some(ref mut local_cell) = cell else {
+        return 0;
+    };
+
+    let refr_index = ref_numbers.next_index(entity_id, local_cell);
+    insert_reference(local_cell, refr_index, ref_id, translation, rotation);
+
+    refr_index
+}
+
+fn insert_reference(
+    cell: &mut Cell,
+    refr_index: u32,
     ref_id: String,
     translation: SV3,
     rotation: [f32; 3],
 ) {
-    let lowest_available_index = lowest_available_index(&used_indices);
-
-    if let Some(ref mut local_cell) = cell {
-        local_cell.references.insert(
-            (0 as u32, lowest_available_index),
-            esp::Reference {
-                id: ref_id.to_owned(),
-                mast_index: 0 as u32,
-                refr_index: lowest_available_index,
-                translation: [translation.x, translation.y, translation.z],
-                rotation: [-rotation[0], -rotation[1], -rotation[2]],
-                ..Default::default()
-            },
-        );
+    cell.references.insert(
+        (0 as u32, refr_index),
+        esp::Reference {
+            id: ref_id.to_owned(),
+            mast_index: 0 as u32,
+            refr_index,
+            translation: [translation.x, translation.y, translation.z],
+            rotation: [-rotation[0], -rotation[1], -rotation[2]],
+            ..Default::default()
+        },
+    );
+}
 
-        used_indices.insert(lowest_available_index);
+fn scale_reference(cell: &mut Cell, refr_index: u32, scale: f32) {
+    if scale != 1.0 {
+        if let Some(reference) = cell.references.get_mut(&(0, refr_index)) {
+            reference.scale = Some(scale);
+        }
+    }
+}
+
+/// Locks, traps and ownership belong to the placed reference rather than the base object
+fn apply_reference_properties(
+    cell: &mut Option<Cell>,
+    refr_index: u32,
+    prop_map: &HashMap<&String, &String>,
+    entity_id: &EntityId,
+) {
+    let Some(reference) = cell
+        .as_mut()
+        .and_then(|cell| cell.references.get_mut(&(0, refr_index)))
+    else {
+        return;
+    };
+
+    let optional_prop = |prop_name: &str| match get_prop(prop_name, prop_map) {
+        value if value.is_empty() => None,
+        value => Some(value),
+    };
+
+    reference.lock_level = get_prop("LockLevel", prop_map).parse::<u32>().ok();
+    reference.key = optional_prop("Key");
+    reference.trap = optional_prop("Trap");
+    reference.owner = optional_prop("Owner");
+    reference.owner_faction = optional_prop("OwnerFaction");
+    reference.global_variable = optional_prop("GlobalVariable");
+
+    if reference.owner.is_some() && reference.owner_faction.is_some() {
+        println!("Warning! Entity {entity_id} is owned by both an NPC and a faction. The game only uses the NPC.");
+    }
+
+    reference.owner_faction_rank = match reference.owner_faction {
+        Some(_) => get_prop("FactionRank", prop_map).parse::<u32>().ok(),
+        None => None,
+    };
+}
+
+/// Properties outside the schema are ignored by the compiler, which usually means a typo
+/// or an fgd which is out of date with this version of morrobroom
+fn warn_unknown_properties(
+    entity_classes: &[fgd::EntityClass],
+    prop_map: &HashMap<&String, &String>,
+    entity_id: &EntityId,
+) {
+    let classname = get_prop("classname", prop_map);
+
+    let Some(known_properties) = fgd::known_properties(entity_classes, &classname) else {
+        return;
+    };
+
+    for key in prop_map.keys() {
+        if !key.starts_with("_tb_") && !known_properties.contains(key.as_str()) {
+            println!("Warning! {classname} {entity_id} has property {key}, which morrobroom does not use. Regenerate your fgd with `morrobroom fgd`.");
+        }
     }
 }
 
@@ -482,6 +1045,11 @@ fn validate_input_map(arg: &str) -> Result<String, String> {
     Ok(arg.into())
 }
 
+fn validate_output_map(arg: &str) -> Result<String, String> {
+    validate_map_extension(arg.as_ref())?;
+    Ok(arg.into())
+}
+
 fn validate_map_extension(path: &Path) -> Result<(), String> {
     let ext = get_extension(path);
     if matches!(&*ext, "map") {
@@ -502,6 +1070,15 @@ fn validate_input_plugin(arg: &str) -> Result<String, String> {
     Ok(arg.into())
 }
 
+fn validate_existing_plugin(arg: &str) -> Result<String, String> {
+    let path = arg.as_ref();
+    validate_plugin_extension(path)?;
+    if !path.exists() {
+        return Err(format!("\"{}\" (file does not exist).", path.display()));
+    }
+    Ok(arg.into())
+}
+
 fn validate_plugin_extension(path: &Path) -> Result<(), String> {
     let ext = get_extension(path);
     if matches!(&*ext, "esp" | "esm" | "omwaddon" | "omwgame") {
@@ -565,7 +1142,17 @@ fn check_morrowind_directory(dir_path: &str) -> Result<String, String> {
     Ok(dir_path.to_string())
 }
 
-fn get_prop(prop_name: &str, prop_map: &HashMap<&String, &String>) -> String {
+/// Groups, layers and detail brushes, including variants like func_detail_wall, have no identity in game
+pub(crate) fn merges_into_worldspawn(classname: &str) -> bool {
+    classname == "func_group" || classname.starts_with("func_detail")
+}
+
+/// Brush classes which make up the worldspawn mesh, and so seal the map
+pub(crate) fn is_world_class(classname: &str) -> bool {
+    classname == "worldspawn" || merges_into_worldspawn(classname)
+}
+
+pub(crate) fn get_prop(prop_name: &str, prop_map: &HashMap<&String, &String>) -> String {
     prop_map
         .get(&prop_name.to_string())
         .unwrap_or(&&String::default())
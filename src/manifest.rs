@@ -0,0 +1,80 @@
+use std::{collections::BTreeMap, fmt::Write, fs, path::Path};
+
+/// Hashes of every file a compile wrote, relative to the output folder.
+/// Two builds of the same map should produce identical manifests.
+pub struct Manifest {
+    workdir: String,
+    files: BTreeMap<String, u64>,
+}
+
+impl Manifest {
+    pub fn new(workdir: &str) -> Manifest {
+        Manifest {
+            workdir: workdir.to_string(),
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Hashes the file as it is on disk, so call it once the file has been saved
+    pub fn record(&mut self, path: &str) {
+        let contents = fs::read(path).expect(&format!("Reading {path} for the manifest failed!"));
+
+        let name = Path::new(path)
+            .strip_prefix(&self.workdir)
+            .unwrap_or(Path::new(path))
+            .to_string_lossy()
+            .replace('\\', "/");
+
+        self.files.insert(name, fnv1a(&contents));
+    }
+
+    pub fn save(&self, path: &str) {
+        let mut contents = String::new();
+        for (name, hash) in &self.files {
+            let _ = writeln!(contents, "{hash:016x}\t{name}");
+        }
+
+        fs::write(path, contents).expect(&format!("Saving {path} failed!"));
+    }
+
+    /// Every difference against an earlier manifest, one line each
+    pub fn verify(&self, path: &str) -> Result<(), Vec<String>> {
+        let contents = fs::read_to_string(path).expect(&format!("Reading manifest {path} failed!"));
+
+        let previous: BTreeMap<String, u64> = contents
+            .lines()
+            .filter_map(|line| {
+                let (hash, name) = line.split_once('\t')?;
+                Some((name.to_string(), u64::from_str_radix(hash, 16).ok()?))
+            })
+            .collect();
+
+        let mut differences = Vec::new();
+
+        for (name, hash) in &self.files {
+            match previous.get(name) {
+                Some(previous_hash) if previous_hash == hash => {}
+                Some(_) => differences.push(format!("{name} changed")),
+                None => differences.push(format!("{name} is new")),
+            }
+        }
+
+        for name in previous.keys() {
+            if !self.files.contains_key(name) {
+                differences.push(format!("{name} was not written"));
+            }
+        }
+
+        match differences.is_empty() {
+            true => Ok(()),
+            false => Err(differences),
+        }
+    }
+}
+
+/// FNV-1a, since std's hasher is randomly seeded per process
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
@@ -2,13 +2,14 @@ use imagesize::size;
 use openmw_cfg::{find_file, get_config, Ini};
 use shalrath::repr::*;
 use shambler::{
+    brush::BrushId,
     entity::EntityId,
     face::{FaceNormals, FaceTriangleIndices, FaceUvs, FaceVertices},
     texture::TextureId,
-    GeoMap, Textures,
+    GeoMap, Textures, Vector3 as SV3,
 };
 use std::{
-    collections::{BTreeMap, HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     fs,
 };
 
@@ -18,7 +19,7 @@ const GRID_SIZE: u8 = 128;
 
 pub struct MapData {
     pub geomap: GeoMap,
-    pub face_grid: HashMap<[i32; 3], Vec<shambler::face::FaceId>>,
+    pub face_grid: BTreeMap<[i32; 3], Vec<shambler::face::FaceId>>,
     pub face_vertices: FaceVertices,
     pub face_tri_indices: FaceTriangleIndices,
     pub inverted_face_tri_indices: FaceTriangleIndices,
@@ -115,7 +116,7 @@ impl MapData {
             &shambler::texture::texture_sizes(&textures_with_paths, texture_sizes),
         );
 
-        let face_grid: HashMap<[i32; 3], Vec<shambler::face::FaceId>> = geomap
+        let face_grid: BTreeMap<[i32; 3], Vec<shambler::face::FaceId>> = geomap
             .brush_faces
             .iter()
             .flat_map(|(_, brush_faces)| {
@@ -133,7 +134,7 @@ impl MapData {
                     (grid_position, *face_id)
                 })
             })
-            .fold(HashMap::new(), |mut acc, (grid_pos, face_id)| {
+            .fold(BTreeMap::new(), |mut acc, (grid_pos, face_id)| {
                 acc.entry(grid_pos).or_insert_with(Vec::new).push(face_id);
                 acc
             });
@@ -150,7 +151,7 @@ impl MapData {
         }
     }
 
-    pub fn collect_textures(textures: &Textures) -> HashSet<String> {
+    pub fn collect_textures(textures: &Textures) -> BTreeSet<String> {
         textures
             .iter()
             .map(|(_, texture_name)| texture_name.to_string())
@@ -176,7 +177,7 @@ impl MapData {
          })
     }
 
-    pub fn find_textures_in_vfs(textures: &HashSet<String>) -> HashSet<String> {
+    pub fn find_textures_in_vfs(textures: &BTreeSet<String>) -> BTreeSet<String> {
         let config = get_config().expect("Openmw.cfg not detected! Please ensure you have a valid openmw configuration file in the canonical system directory.");
         textures
             .iter()
@@ -184,6 +185,169 @@ impl MapData {
             .collect()
     }
 
+    /// Removes everything in layers which TrenchBroom marks as omitted from export, and
+    /// everything in hidden layers when `skip_hidden` is set. Layer brushes belong to the
+    /// layer's own entity, while entities and groups name their layer with `_tb_layer`.
+    /// Returns the names of the layers which were left out.
+    pub fn exclude_layers(&mut self, skip_hidden: bool) -> Vec<String> {
+        let entities: Vec<(EntityId, HashMap<String, String>)> = self
+            .geomap
+            .entity_properties
+            .keys()
+            .map(|entity_id| {
+                let props = self
+                    .get_entity_properties(entity_id)
+                    .into_iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect();
+                (*entity_id, props)
+            })
+            .collect();
+
+        let prop = |props: &HashMap<String, String>, key: &str| {
+            props.get(key).cloned().unwrap_or_default()
+        };
+
+        let mut excluded_layers = HashSet::new();
+        let mut layer_names = Vec::new();
+        let mut groups = HashMap::new();
+
+        for (_, props) in &entities {
+            match prop(props, "_tb_type").as_str() {
+                "_tb_layer" => {
+                    if prop(props, "_tb_layer_omit_from_export") == "1"
+                        || (skip_hidden && prop(props, "_tb_layer_hidden") == "1")
+                    {
+                        excluded_layers.insert(prop(props, "_tb_id"));
+                        layer_names.push(prop(props, "_tb_name"));
+                    }
+                }
+                "_tb_group" => {
+                    groups.insert(prop(props, "_tb_id"), props);
+                }
+                _ => {}
+            }
+        }
+
+        if excluded_layers.is_empty() {
+            return layer_names;
+        }
+
+        // Grouped entities only know their group, so the layer is found through the group,
+        // which may itself be inside another group
+        let layer_of = |props: &HashMap<String, String>| {
+            let mut props = props;
+            for _ in 0..=groups.len() {
+                if let Some(layer) = props.get("_tb_layer") {
+                    return Some(layer.to_string());
+                }
+                props = groups.get(props.get("_tb_group")?)?;
+            }
+            None
+        };
+
+        let excluded: HashSet<EntityId> = entities
+            .iter()
+            .filter(|(_, props)| {
+                let is_excluded_layer = prop(props, "_tb_type") == "_tb_layer"
+                    && excluded_layers.contains(&prop(props, "_tb_id"));
+                is_excluded_layer
+                    || layer_of(props).is_some_and(|layer| excluded_layers.contains(&layer))
+            })
+            .map(|(entity_id, _)| *entity_id)
+            .collect();
+
+        self.geomap
+            .entity_brushes
+            .retain(|entity_id, _| !excluded.contains(entity_id));
+        self.geomap
+            .point_entities
+            .retain(|entity_id| !excluded.contains(entity_id));
+
+        layer_names
+    }
+
+    /// Brush entities in map order. The geomap stores them unordered, which would
+    /// otherwise make the output differ between builds of the same map.
+    pub fn brush_entities(&self) -> Vec<(&EntityId, &Vec<BrushId>)> {
+        let mut entities: Vec<_> = self.geomap.entity_brushes.iter().collect();
+        entities.sort_by_key(|(entity_id, _)| **entity_id);
+        entities
+    }
+
+    /// Point entities in map order
+    pub fn point_entities(&self) -> Vec<&EntityId> {
+        let mut entities: Vec<_> = self.geomap.point_entities.iter().collect();
+        entities.sort();
+        entities
+    }
+
+    /// Origin brushes only mark an entity's pivot and are never rendered.
+    pub fn brush_is_origin(&self, brush_id: &BrushId) -> bool {
+        self.geomap
+            .brush_faces
+            .get(brush_id)
+            .expect("Brush faces should always be valid")
+            .iter()
+            .all(|face_id| {
+                let texture_id = self.geomap.face_textures.get(face_id).unwrap();
+                self.geomap.textures.get(texture_id).unwrap() == "origin"
+            })
+    }
+
+    pub fn brush_center(&self, brush_id: &BrushId) -> SV3 {
+        Mesh::centroid(&self.brush_vertices(brush_id))
+    }
+
+    /// Smallest and largest corner of the box around all of the given brushes
+    pub fn brush_bounds(&self, brushes: &[BrushId]) -> (SV3, SV3) {
+        brushes
+            .iter()
+            .flat_map(|brush_id| self.brush_vertices(brush_id))
+            .fold(
+                (SV3::repeat(f32::MAX), SV3::repeat(f32::MIN)),
+                |(mins, maxs), vert| (mins.inf(&vert), maxs.sup(&vert)),
+            )
+    }
+
+    /// Brushes are convex, so a point is inside when it is behind every face
+    pub fn brush_contains(&self, brush_id: &BrushId, point: SV3) -> bool {
+        self.brush_planes(brush_id)
+            .iter()
+            .all(|(vertex, normal)| (point - vertex).dot(normal) <= 0.01)
+    }
+
+    /// A point on each face of the brush, and the face's outward normal
+    pub fn brush_planes(&self, brush_id: &BrushId) -> Vec<(SV3, SV3)> {
+        self.geomap
+            .brush_faces
+            .get(brush_id)
+            .expect("Brush faces should always be valid")
+            .iter()
+            .filter_map(|face_id| {
+                let vertex = self.face_vertices.get(face_id)?.first()?;
+                let normal = self.flat_normals.get(face_id)?.first()?;
+                Some((*vertex, *normal))
+            })
+            .collect()
+    }
+
+    fn brush_vertices(&self, brush_id: &BrushId) -> Vec<SV3> {
+        self.geomap
+            .brush_faces
+            .get(brush_id)
+            .expect("Brush faces should always be valid")
+            .iter()
+            .flat_map(|face_id| {
+                self.face_vertices
+                    .get(face_id)
+                    .expect("Face vertices should always be valid")
+                    .iter()
+                    .copied()
+            })
+            .collect()
+    }
+
     pub fn get_entity_properties(&self, entity_id: &EntityId) -> HashMap<&String, &String> {
         let entity_properties = self.geomap.entity_properties.get(&entity_id);
 
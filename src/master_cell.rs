@@ -0,0 +1,161 @@
+use openmw_cfg::{find_file, get_config};
+use shambler::Vector3 as SV3;
+use std::{fs, path::PathBuf};
+use tes3::esp::{Cell, CellFlags, Header, Plugin, Reference};
+
+use crate::{get_prop, MapData};
+
+/// An interior cell from a master file which the map adds to, rather than replacing.
+/// Its own references stay in the master; only the map's references go into the plugin.
+pub struct MasterCell {
+    pub master: String,
+    pub cell: Cell,
+    /// The references the master places in the cell, in reference order
+    pub references: Vec<Reference>,
+    /// Moves the map into the master cell's coordinates, in game units
+    pub offset: SV3,
+    master_size: u64,
+}
+
+impl MasterCell {
+    /// Reads the worldspawn `Master` property. Maps without one build their own cell.
+    pub fn load(map_data: &MapData, scale_mode: &f32) -> Option<MasterCell> {
+        let worldspawn = map_data
+            .brush_entities()
+            .into_iter()
+            .map(|(entity_id, _)| map_data.get_entity_properties(entity_id))
+            .find(|prop_map| get_prop("classname", prop_map) == "worldspawn")?;
+
+        let master = get_prop("Master", &worldspawn);
+        if master.is_empty() {
+            return None;
+        }
+
+        let cell_name = get_prop("Name", &worldspawn);
+        if cell_name.is_empty() {
+            panic!("Worldspawn names {master} as its master, but not which of its cells to add to! Set Name to the cell's name.");
+        }
+
+        let master_path = find_master(&master);
+        // Headers list masters by file name, wherever they were found
+        let master = master_path
+            .file_name()
+            .map_or(master.clone(), |name| name.to_string_lossy().to_string());
+        let master_size = fs::metadata(&master_path)
+            .expect(&format!("Reading {} failed!", master_path.display()))
+            .len();
+        let plugin = Plugin::from_path(&master_path)
+            .expect(&format!("Reading {} failed!", master_path.display()));
+
+        let master_cell = plugin
+            .objects_of_type::<Cell>()
+            .find(|cell| {
+                cell.data.flags.contains(CellFlags::IS_INTERIOR)
+                    && cell.name.eq_ignore_ascii_case(&cell_name)
+            })
+            .expect(&format!(
+                "No interior cell named {cell_name} was found in {master}!"
+            ));
+
+        let offset = anchor_offset(map_data, master_cell, scale_mode);
+
+        let mut references: Vec<Reference> = master_cell
+            .references
+            .values()
+            .filter(|reference| reference.deleted.is_none())
+            .cloned()
+            .collect();
+        references.sort_by_key(|reference| (reference.mast_index, reference.refr_index));
+
+        // The cell's own settings are left as the master has them
+        let cell = Cell {
+            references: Default::default(),
+            ..master_cell.clone()
+        };
+
+        Some(MasterCell {
+            master,
+            cell,
+            references,
+            offset,
+            master_size,
+        })
+    }
+
+    /// References made by this map are numbered by the plugin itself, so only the
+    /// header needs to know about the master. Returns the mast_index the master's own
+    /// references have in this plugin.
+    pub fn add_to_header(&self, plugin: &mut Plugin) -> u32 {
+        let header = plugin
+            .objects_of_type_mut::<Header>()
+            .next()
+            .expect("Headers are created before masters are added");
+
+        let position = match header
+            .masters
+            .iter()
+            .position(|(name, _)| name.eq_ignore_ascii_case(&self.master))
+        {
+            Some(position) => position,
+            None => {
+                header.masters.push((self.master.clone(), self.master_size));
+                header.masters.len() - 1
+            }
+        };
+
+        position as u32 + 1
+    }
+}
+
+/// An `info_master_anchor` marks where a reference from the master cell sits in the map.
+/// Without one, map coordinates are used as they are.
+fn anchor_offset(map_data: &MapData, master_cell: &Cell, scale_mode: &f32) -> SV3 {
+    let Some(anchor) = map_data
+        .point_entities()
+        .into_iter()
+        .map(|entity_id| map_data.get_entity_properties(entity_id))
+        .find(|prop_map| get_prop("classname", prop_map) == "info_master_anchor")
+    else {
+        return SV3::zeros();
+    };
+
+    let ref_id = get_prop("RefId", &anchor);
+    let occurrence = get_prop("Occurrence", &anchor)
+        .parse::<usize>()
+        .unwrap_or(1);
+
+    let mut references: Vec<_> = master_cell
+        .references
+        .values()
+        .filter(|reference| reference.id.eq_ignore_ascii_case(&ref_id))
+        .collect();
+    references.sort_by_key(|reference| (reference.mast_index, reference.refr_index));
+
+    let reference = references.get(occurrence.max(1) - 1).expect(&format!(
+        "info_master_anchor looks for {ref_id} number {occurrence} in {}, which has {} of them!",
+        master_cell.name,
+        references.len()
+    ));
+
+    let origin: Vec<f32> = get_prop("origin", &anchor)
+        .split_whitespace()
+        .map(|axis| axis.parse::<f32>().unwrap_or_default())
+        .collect();
+    let anchor_position = match origin[..] {
+        [x, y, z] => SV3::new(x, y, z) * *scale_mode,
+        _ => SV3::zeros(),
+    };
+
+    SV3::from(reference.translation) - anchor_position
+}
+
+/// Masters are looked up in the OpenMW data folders, or used as a path if they aren't there
+fn find_master(master: &str) -> PathBuf {
+    let config =
+        get_config().expect("Openmw.cfg not located! Be sure you have a valid openmw setup.");
+
+    match find_file(&config, master) {
+        Ok(path) => path,
+        Err(_) => PathBuf::from(master),
+    }
+}
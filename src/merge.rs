@@ -0,0 +1,602 @@
+use std::{
+    collections::{HashMap, HashSet},
+    mem::{discriminant, Discriminant},
+    path::{Path, PathBuf},
+};
+
+use tes3::esp::{Cell, EditorId, Plugin, Reference, TES3Object};
+
+use crate::game_object;
+
+type RecordKey = (Discriminant<TES3Object>, String);
+
+/// What morrobroom wrote into the plugin on its last compile of one map, stored beside it
+/// as `<plugin>.<map>.mbbase`. Anything which differs from it in the plugin was edited by
+/// hand. Each map keeps its own base, so maps sharing a plugin never remove each other's records.
+pub struct MergeBase {
+    path: PathBuf,
+    records: HashMap<RecordKey, TES3Object>,
+}
+
+impl MergeBase {
+    /// Plugins compiled before merging existed have no base. The old behaviour is kept for
+    /// them: every record this compile regenerates, and every point light, is replaced.
+    pub fn load(plugin_name: &str, plugin: &Plugin, map_dir: &str) -> MergeBase {
+        let path = Path::new(plugin_name).with_extension(format!("{map_dir}.mbbase"));
+
+        let records = match Plugin::from_path(&path) {
+            Ok(base) => base.objects,
+            Err(_) => plugin
+                .objects
+                .iter()
+                .filter(|object| object.editor_id().contains(&format!("{map_dir}-PL")))
+                .cloned()
+                .collect(),
+        };
+
+        MergeBase {
+            path,
+            records: records
+                .into_iter()
+                .map(|object| (record_key(&object), object))
+                .collect(),
+        }
+    }
+
+    /// References in the cell which morrobroom didn't place, so their numbers must not be reused
+    pub fn hand_placed_references(&self, cell: &Cell) -> HashSet<u32> {
+        let base_references = match self
+            .records
+            .get(&record_key(&TES3Object::Cell(cell.clone())))
+        {
+            Some(TES3Object::Cell(base)) => Some(&base.references),
+            _ => None,
+        };
+
+        cell.references
+            .keys()
+            .filter(|key| key.0 == 0)
+            .filter(|key| !base_references.is_some_and(|references| references.contains_key(key)))
+            .map(|key| key.1)
+            .collect()
+    }
+
+    pub fn save(&self, created_objects: &[TES3Object]) {
+        let mut base = Plugin::default();
+        base.objects.extend(created_objects.iter().cloned());
+        base.save_path(&self.path)
+            .expect(&format!("Saving {} failed!", self.path.display()));
+    }
+}
+
+/// Merges a fresh compile into the plugin. Records and reference fields morrobroom wrote
+/// last time are updated; anything else in the plugin is left alone. When the map and the
+/// plugin both changed the same thing, the plugin wins and the conflict is returned.
+pub fn merge(
+    plugin: &mut Plugin,
+    base: &MergeBase,
+    created_objects: Vec<TES3Object>,
+) -> Vec<String> {
+    let mut conflicts = Vec::new();
+    let mut base_records = base.records.clone();
+
+    let mut ours: HashMap<RecordKey, TES3Object> = HashMap::new();
+    let mut our_order = Vec::new();
+    for object in created_objects {
+        let key = record_key(&object);
+        our_order.push(key.clone());
+        ours.insert(key, object);
+    }
+
+    let mut merged = Vec::new();
+
+    for theirs in plugin.objects.drain(..) {
+        let key = record_key(&theirs);
+
+        match (base_records.remove(&key), ours.remove(&key)) {
+            // Made by hand
+            (None, None) => merged.push(theirs),
+            // Never written by morrobroom before, so it is taken over
+            (None, Some(ours)) => merged.push(ours),
+            (Some(base), None) => match base == theirs {
+                true => {} // Removed from the map
+                false => {
+                    conflicts.push(format!(
+                        "{} was removed from the map but edited in the plugin, so it was kept.",
+                        key.1
+                    ));
+                    merged.push(theirs);
+                }
+            },
+            (Some(base), Some(ours)) => {
+                merged.push(merge_record(base, ours, theirs, &mut conflicts))
+            }
+        }
+    }
+
+    for key in our_order {
+        let Some(ours) = ours.remove(&key) else {
+            continue;
+        };
+
+        match base_records.remove(&key) {
+            None => merged.push(ours),
+            Some(base) if base == ours => {} // Deleted from the plugin by hand
+            Some(_) => conflicts.push(format!(
+                "{} was deleted from the plugin but changed in the map, so it stays deleted.",
+                key.1
+            )),
+        }
+    }
+
+    plugin.objects = merged;
+    conflicts
+}
+
+fn merge_record(
+    base: TES3Object,
+    ours: TES3Object,
+    theirs: TES3Object,
+    conflicts: &mut Vec<String>,
+) -> TES3Object {
+    if let (TES3Object::Cell(base), TES3Object::Cell(ours), TES3Object::Cell(theirs)) =
+        (&base, &ours, &theirs)
+    {
+        return TES3Object::Cell(merge_cell(base, ours, theirs, conflicts));
+    }
+
+    if let Some(merged) = three_way(&base, &ours, &theirs) {
+        return merged.clone();
+    }
+
+    // Scripts are often attached by hand, so they merge apart from the rest of the record
+    let (base_script, base_rest) = split_script(&base);
+    let (our_script, our_rest) = split_script(&ours);
+    let (their_script, their_rest) = split_script(&theirs);
+
+    match (
+        three_way(&base_script, &our_script, &their_script),
+        three_way(&base_rest, &our_rest, &their_rest),
+    ) {
+        (Some(Some(script)), Some(rest)) => {
+            let mut merged = rest.clone();
+            if let Some(field) = game_object::script_field(&mut merged) {
+                *field = script.clone();
+            }
+            merged
+        }
+        _ => {
+            conflicts.push(format!(
+                "{} was changed in both the map and the plugin, so the plugin's version was kept.",
+                theirs.editor_id()
+            ));
+            theirs
+        }
+    }
+}
+
+/// References are merged one by one, keyed by their reference number
+fn merge_cell(base: &Cell, ours: &Cell, theirs: &Cell, conflicts: &mut Vec<String>) -> Cell {
+    let without_references = |cell: &Cell| Cell {
+        references: Default::default(),
+        ..cell.clone()
+    };
+
+    let mut merged = match three_way(
+        &without_references(base),
+        &without_references(ours),
+        &without_references(theirs),
+    ) {
+        Some(cell) => cell.clone(),
+        None => {
+            conflicts.push(format!(
+                "Cell {} was changed in both the map and the plugin, so the plugin's cell settings were kept.",
+                theirs.name
+            ));
+            without_references(theirs)
+        }
+    };
+
+    let mut keys: Vec<&(u32, u32)> = base
+        .references
+        .keys()
+        .chain(ours.references.keys())
+        .chain(theirs.references.keys())
+        .collect();
+    keys.sort();
+    keys.dedup();
+
+    for key in keys {
+        let reference = match (
+            base.references.get(key),
+            ours.references.get(key),
+            theirs.references.get(key),
+        ) {
+            (_, None, None) => None,
+            (None, Some(ours), None) => Some(ours.clone()),
+            (None, None, Some(theirs)) => Some(theirs.clone()),
+            (None, Some(ours), Some(_)) => Some(ours.clone()),
+            (Some(base), None, Some(theirs)) => match base == theirs {
+                true => None,
+                false => {
+                    conflicts.push(format!(
+                        "Reference {} to {} was removed from the map but edited in the plugin, so it was kept.",
+                        key.1, theirs.id
+                    ));
+                    Some(theirs.clone())
+                }
+            },
+            (Some(base), Some(ours), None) => {
+                if base != ours {
+                    conflicts.push(format!(
+                        "Reference {} to {} was deleted from the plugin but changed in the map, so it stays deleted.",
+                        key.1, ours.id
+                    ));
+                }
+                None
+            }
+            (Some(base), Some(ours), Some(theirs)) => {
+                let mut fields = Vec::new();
+                let reference = merge_reference(base, ours, theirs, &mut fields);
+                if !fields.is_empty() {
+                    conflicts.push(format!(
+                        "Reference {} to {} has {} changed in both the map and the plugin, so the plugin's values were kept.",
+                        key.1,
+                        theirs.id,
+                        fields.join(", ")
+                    ));
+                }
+                Some(reference)
+            }
+        };
+
+        if let Some(reference) = reference {
+            merged.references.insert(*key, reference);
+        }
+    }
+
+    merged
+}
+
+/// Only the fields morrobroom writes are merged. Everything else comes from the plugin.
+fn merge_reference(
+    base: &Reference,
+    ours: &Reference,
+    theirs: &Reference,
+    conflicts: &mut Vec<&'static str>,
+) -> Reference {
+    let mut merged = theirs.clone();
+
+    macro_rules! merge_fields {
+        ($($field:ident),*) => {
+            $(
+                match three_way(&base.$field, &ours.$field, &theirs.$field) {
+                    Some(value) => merged.$field = value.clone(),
+                    None => conflicts.push(stringify!($field)),
+                }
+            )*
+        };
+    }
+
+    merge_fields!(
+        id,
+        translation,
+        rotation,
+        scale,
+        lock_level,
+        key,
+        trap,
+        owner,
+        owner_faction,
+        owner_faction_rank,
+        global_variable
+    );
+
+    merged
+}
+
+/// The side which changed, or None if both did so differently
+fn three_way<'a, T: PartialEq>(base: &'a T, ours: &'a T, theirs: &'a T) -> Option<&'a T> {
+    if theirs == base {
+        Some(ours)
+    } else if ours == base || ours == theirs {
+        Some(theirs)
+    } else {
+        None
+    }
+}
+
+fn split_script(object: &TES3Object) -> (Option<String>, TES3Object) {
+    let mut rest = object.clone();
+    let script = game_object::script_field(&mut rest).map(std::mem::take);
+    (script, rest)
+}
+
+fn record_key(object: &TES3Object) -> RecordKey {
+    (
+        discriminant(object),
+        object.editor_id().to_ascii_lowercase(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tes3::esp::{Activator, Static};
+
+    fn base(records: Vec<TES3Object>) -> MergeBase {
+        MergeBase {
+            path: PathBuf::new(),
+            records: records
+                .into_iter()
+                .map(|object| (record_key(&object), object))
+                .collect(),
+        }
+    }
+
+    fn plugin(objects: Vec<TES3Object>) -> Plugin {
+        let mut plugin = Plugin::default();
+        plugin.objects = objects;
+        plugin
+    }
+
+    fn static_object(id: &str, mesh: &str) -> TES3Object {
+        TES3Object::Static(Static {
+            id: id.to_string(),
+            mesh: mesh.to_string(),
+            ..Default::default()
+        })
+    }
+
+    fn activator(id: &str, mesh: &str, script: &str) -> TES3Object {
+        TES3Object::Activator(Activator {
+            id: id.to_string(),
+            mesh: mesh.to_string(),
+            script: script.to_string(),
+            ..Default::default()
+        })
+    }
+
+    fn reference(id: &str, translation: [f32; 3]) -> Reference {
+        Reference {
+            id: id.to_string(),
+            refr_index: 1,
+            translation,
+            ..Default::default()
+        }
+    }
+
+    fn cell(references: Vec<Reference>) -> Cell {
+        Cell {
+            name: "test".to_string(),
+            references: references
+                .into_iter()
+                .map(|reference| ((reference.mast_index, reference.refr_index), reference))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn merged_cell(plugin: &Plugin) -> &Cell {
+        match &plugin.objects[..] {
+            [TES3Object::Cell(cell)] => cell,
+            _ => panic!("Expected a single cell"),
+        }
+    }
+
+    #[test]
+    fn three_way_takes_the_side_which_changed() {
+        assert_eq!(three_way(&1, &2, &1), Some(&2));
+        assert_eq!(three_way(&1, &1, &3), Some(&3));
+        assert_eq!(three_way(&1, &2, &2), Some(&2));
+        assert_eq!(three_way(&1, &2, &3), None);
+    }
+
+    #[test]
+    fn new_records_are_added() {
+        let mut plugin = plugin(vec![static_object("hand", "hand.nif")]);
+        let conflicts = merge(
+            &mut plugin,
+            &base(vec![]),
+            vec![static_object("map-a", "a.nif")],
+        );
+
+        assert!(conflicts.is_empty());
+        assert_eq!(
+            plugin.objects,
+            vec![
+                static_object("hand", "hand.nif"),
+                static_object("map-a", "a.nif")
+            ]
+        );
+    }
+
+    #[test]
+    fn records_removed_from_the_map_are_removed() {
+        let mut plugin = plugin(vec![static_object("map-a", "a.nif")]);
+        let conflicts = merge(
+            &mut plugin,
+            &base(vec![static_object("map-a", "a.nif")]),
+            vec![],
+        );
+
+        assert!(conflicts.is_empty());
+        assert!(plugin.objects.is_empty());
+    }
+
+    #[test]
+    fn records_from_other_maps_are_kept() {
+        let mut plugin = plugin(vec![
+            static_object("other-a", "a.nif"),
+            static_object("map-b", "b.nif"),
+        ]);
+        let conflicts = merge(
+            &mut plugin,
+            &base(vec![static_object("map-b", "b.nif")]),
+            vec![static_object("map-b", "b.nif")],
+        );
+
+        assert!(conflicts.is_empty());
+        assert_eq!(
+            plugin.objects,
+            vec![
+                static_object("other-a", "a.nif"),
+                static_object("map-b", "b.nif")
+            ]
+        );
+    }
+
+    #[test]
+    fn edited_records_removed_from_the_map_are_kept() {
+        let mut plugin = plugin(vec![static_object("map-a", "edited.nif")]);
+        let conflicts = merge(
+            &mut plugin,
+            &base(vec![static_object("map-a", "a.nif")]),
+            vec![],
+        );
+
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(plugin.objects, vec![static_object("map-a", "edited.nif")]);
+    }
+
+    #[test]
+    fn edits_from_either_side_are_kept() {
+        let mut plugin = plugin(vec![
+            static_object("map-a", "a.nif"),
+            static_object("map-b", "edited.nif"),
+        ]);
+        let conflicts = merge(
+            &mut plugin,
+            &base(vec![
+                static_object("map-a", "a.nif"),
+                static_object("map-b", "b.nif"),
+            ]),
+            vec![
+                static_object("map-a", "changed.nif"),
+                static_object("map-b", "b.nif"),
+            ],
+        );
+
+        assert!(conflicts.is_empty());
+        assert_eq!(
+            plugin.objects,
+            vec![
+                static_object("map-a", "changed.nif"),
+                static_object("map-b", "edited.nif")
+            ]
+        );
+    }
+
+    #[test]
+    fn conflicting_edits_keep_the_plugin_version() {
+        let mut plugin = plugin(vec![static_object("map-a", "edited.nif")]);
+        let conflicts = merge(
+            &mut plugin,
+            &base(vec![static_object("map-a", "a.nif")]),
+            vec![static_object("map-a", "changed.nif")],
+        );
+
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(plugin.objects, vec![static_object("map-a", "edited.nif")]);
+    }
+
+    #[test]
+    fn scripts_attached_by_hand_survive_map_changes() {
+        let mut plugin = plugin(vec![activator("map-a", "a.nif", "handScript")]);
+        let conflicts = merge(
+            &mut plugin,
+            &base(vec![activator("map-a", "a.nif", "")]),
+            vec![activator("map-a", "changed.nif", "")],
+        );
+
+        assert!(conflicts.is_empty());
+        assert_eq!(
+            plugin.objects,
+            vec![activator("map-a", "changed.nif", "handScript")]
+        );
+    }
+
+    #[test]
+    fn reference_fields_merge_separately() {
+        let original = reference("chair", [0.0, 0.0, 0.0]);
+        let moved = reference("chair", [64.0, 0.0, 0.0]);
+        let scaled = Reference {
+            scale: Some(2.0),
+            ..original.clone()
+        };
+
+        let mut plugin = plugin(vec![TES3Object::Cell(cell(vec![scaled]))]);
+        let conflicts = merge(
+            &mut plugin,
+            &base(vec![TES3Object::Cell(cell(vec![original]))]),
+            vec![TES3Object::Cell(cell(vec![moved]))],
+        );
+
+        assert!(conflicts.is_empty());
+        let merged = &merged_cell(&plugin).references[&(0, 1)];
+        assert_eq!(merged.translation, [64.0, 0.0, 0.0]);
+        assert_eq!(merged.scale, Some(2.0));
+    }
+
+    #[test]
+    fn conflicting_reference_fields_keep_the_plugin_value() {
+        let mut plugin = plugin(vec![TES3Object::Cell(cell(vec![reference(
+            "chair",
+            [0.0, 32.0, 0.0],
+        )]))]);
+        let conflicts = merge(
+            &mut plugin,
+            &base(vec![TES3Object::Cell(cell(vec![reference(
+                "chair",
+                [0.0, 0.0, 0.0],
+            )]))]),
+            vec![TES3Object::Cell(cell(vec![reference(
+                "chair",
+                [64.0, 0.0, 0.0],
+            )]))],
+        );
+
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(
+            merged_cell(&plugin).references[&(0, 1)].translation,
+            [0.0, 32.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn references_follow_the_map_when_untouched() {
+        let kept = reference("chair", [0.0, 0.0, 0.0]);
+        let added = Reference {
+            refr_index: 2,
+            ..reference("table", [0.0, 0.0, 0.0])
+        };
+        let removed = Reference {
+            refr_index: 3,
+            ..reference("lamp", [0.0, 0.0, 0.0])
+        };
+        let hand_placed = Reference {
+            refr_index: 4,
+            ..reference("rug", [0.0, 0.0, 0.0])
+        };
+
+        let mut plugin = plugin(vec![TES3Object::Cell(cell(vec![
+            kept.clone(),
+            removed.clone(),
+            hand_placed.clone(),
+        ]))]);
+        let conflicts = merge(
+            &mut plugin,
+            &base(vec![TES3Object::Cell(cell(vec![kept.clone(), removed]))]),
+            vec![TES3Object::Cell(cell(vec![kept, added]))],
+        );
+
+        assert!(conflicts.is_empty());
+        let mut indices: Vec<u32> = merged_cell(&plugin)
+            .references
+            .keys()
+            .map(|key| key.1)
+            .collect();
+        indices.sort();
+        assert_eq!(indices, vec![1, 2, 4]);
+    }
+}
@@ -1,16 +1,18 @@
-use nalgebra::{Rotation3, Vector3};
+use nalgebra::{Rotation3, UnitQuaternion, Vector3};
 use openmw_cfg::{find_file, get_config};
 use shambler::{brush::BrushId, entity::EntityId, Vector3 as SV3};
 use tes3::{
     esp,
     nif::{
-        self, NiAlphaProperty, NiLink, NiMaterialProperty, NiNode, NiStream, NiTriShape,
-        NiTriShapeData, RootCollisionNode,
+        self, KeyType, NiAlphaProperty, NiFloatData, NiKeyframeController, NiKeyframeData, NiLink,
+        NiMaterialProperty, NiNode, NiStream, NiTextKey, NiTextKeyExtraData, NiTriShape,
+        NiTriShapeData, NiUVController, NiUVData, RootCollisionNode,
     },
 };
 
 use crate::{
-    brush_ni_node::{BrushNiAlphaProps, BrushNiMatProps},
+    brush_ni_node::{BrushNiAlphaProps, BrushNiMatProps, BrushNiUVProps},
+    mover::Mover,
     BrushNiNode, MapData,
 };
 
@@ -22,6 +24,9 @@ pub struct Mesh {
     pub base_index: NiLink<NiNode>,
     pub final_distance: SV3,
     pub mangle: [f32; 3],
+    /// Overrides the vertex centroid as the object's origin, in unscaled map units
+    pub pivot: Option<SV3>,
+    root_index: NiLink<NiNode>,
     collision_index: NiLink<RootCollisionNode>,
 }
 
@@ -47,11 +52,21 @@ impl Mesh {
         Mesh {
             stream,
             base_index,
+            root_index,
             collision_index,
             game_object: esp::TES3Object::Static(esp::Static::default()),
             node_distances: Vec::new(),
             final_distance: SV3::default(),
             mangle: [0.0, 0.0, 0.0],
+            pivot: None,
+        }
+    }
+
+    /// Mesh with no geometry, for objects which only need to exist in the cell
+    pub fn marker(scale_mode: &f32) -> Mesh {
+        Mesh {
+            pivot: Some(SV3::default()),
+            ..Mesh::new(scale_mode)
         }
     }
 
@@ -64,6 +79,11 @@ impl Mesh {
         let mut mesh = Mesh::new(scale_mode);
 
         for brush_id in brushes {
+            if map_data.brush_is_origin(brush_id) {
+                mesh.pivot = Some(map_data.brush_center(brush_id));
+                continue;
+            }
+
             let brush_nodes = BrushNiNode::from_brush(brush_id, entity_id, map_data);
 
             for node in brush_nodes {
@@ -73,8 +93,16 @@ impl Mesh {
         mesh
     }
 
+    /// Position of the object's origin in unscaled map units
+    pub fn origin(&self) -> SV3 {
+        match self.pivot {
+            Some(pivot) => pivot,
+            None => Mesh::centroid(&self.node_distances),
+        }
+    }
+
     pub fn align_to_center(&mut self) {
-        let center = Mesh::centroid(&self.node_distances);
+        let center = self.origin();
         let rotation = Rotation3::new(Vector3::new(
             -self.mangle[0],
             -self.mangle[1],
@@ -129,6 +157,8 @@ impl Mesh {
 
             self.assign_material(node.mat_props, vis_index);
 
+            self.assign_uv_controller(node.uv_props, vis_index);
+
             vis_data_index = self.stream.insert(node.vis_data);
 
             if let Some(shape) = self.stream.get_mut(vis_index) {
@@ -237,4 +267,96 @@ impl Mesh {
             .properties
             .push(mat_link.cast());
     }
+
+    pub fn assign_uv_controller(&mut self, props: BrushNiUVProps, object: NiLink<NiTriShape>) {
+        if props == BrushNiUVProps::default() {
+            return;
+        }
+
+        let period = props.period.unwrap_or(1.0).max(f32::EPSILON);
+        let tile_u = props.tile_u.unwrap_or(1.0);
+        let tile_v = props.tile_v.unwrap_or(1.0);
+
+        // Offsets are in texture repeats, so whole-number scroll speeds loop without a seam
+        let mut uv_data = NiUVData::default();
+        uv_data.u_offset_data =
+            Self::linear_float_data(period, 0.0, props.scroll_u.unwrap_or_default() * period);
+        uv_data.v_offset_data =
+            Self::linear_float_data(period, 0.0, props.scroll_v.unwrap_or_default() * period);
+        uv_data.u_tiling_data = Self::linear_float_data(period, tile_u, tile_u);
+        uv_data.v_tiling_data = Self::linear_float_data(period, tile_v, tile_v);
+
+        let data_link = self.stream.insert(uv_data);
+
+        let mut controller = NiUVController::default();
+        controller.flags = 8; // Active, cycle type loop
+        controller.frequency = 1.0;
+        controller.start_time = 0.0;
+        controller.stop_time = period;
+        controller.target = object.cast();
+        controller.data = data_link;
+
+        let controller_link = self.stream.insert(controller);
+
+        self.stream
+            .get_mut(object)
+            .expect("Self retreival should never fail")
+            .controller = controller_link.cast();
+    }
+
+    pub fn linear_float_data(duration: f32, start: f32, end: f32) -> NiFloatData {
+        let mut data = NiFloatData::default();
+        data.key_type = KeyType::LinKey;
+        data.keys = vec![[0.0, start].into(), [duration, end].into()];
+        data
+    }
+
+    pub fn attach_mover(&mut self, mover: &Mover) {
+        self.attach_keyframe_controller(mover.rotation_keys());
+        self.attach_text_keys(mover.text_keys());
+    }
+
+    pub fn attach_keyframe_controller(&mut self, rotation_keys: Vec<(f32, UnitQuaternion<f32>)>) {
+        let stop_time = rotation_keys.last().map_or(0.0, |(time, _)| *time);
+
+        let mut keyframe_data = NiKeyframeData::default();
+        keyframe_data.rotation_type = KeyType::LinKey;
+        keyframe_data.rotation_keys = rotation_keys
+            .iter()
+            .map(|(time, rotation)| [*time, rotation.w, rotation.i, rotation.j, rotation.k].into())
+            .collect();
+
+        let data_link = self.stream.insert(keyframe_data);
+
+        let mut controller = NiKeyframeController::default();
+        controller.flags = 8; // Active, cycle type loop
+        controller.frequency = 1.0;
+        controller.start_time = 0.0;
+        controller.stop_time = stop_time;
+        controller.target = self.base_index.cast();
+        controller.data = data_link;
+
+        let controller_link = self.stream.insert(controller);
+
+        self.stream
+            .get_mut(self.base_index)
+            .expect("Self retreival should never fail")
+            .controller = controller_link.cast();
+    }
+
+    /// Text keys have to live on the root node for PlayGroup to find them
+    pub fn attach_text_keys(&mut self, text_keys: Vec<(f32, String)>) {
+        let mut extra_data = NiTextKeyExtraData::default();
+        extra_data.keys = text_keys
+            .into_iter()
+            .map(|(time, value)| NiTextKey { time, value })
+            .collect();
+
+        let extra_data_link = self.stream.insert(extra_data);
+
+        self.stream
+            .get_mut(self.root_index)
+            .expect("Self retreival should never fail")
+            .extra_data = extra_data_link.cast();
+    }
 }
@@ -0,0 +1,359 @@
+use std::{fmt::Write, fs, path::Path};
+
+use clap::ArgMatches;
+use nalgebra::{Matrix3, Vector3};
+use openmw_cfg::{find_file, get_config, Ini};
+use tes3::{
+    esp::{EditorId, Plugin, TES3Object},
+    nif::{
+        NiBSAnimationNode, NiBSPArrayNode, NiBSParticleNode, NiBillboardNode, NiLink, NiNode,
+        NiSortAdjustNode, NiSourceTexture, NiStream, NiSwitchNode, NiTexturingProperty, NiTriShape,
+        NiTriShapeData, RootCollisionNode, TextureMap, TextureSource,
+    },
+};
+
+use crate::map_data::MapData;
+
+/// Accumulated parent transform while walking the node tree
+#[derive(Clone, Copy)]
+struct Transform {
+    translation: Vector3<f32>,
+    rotation: Matrix3<f32>,
+    scale: f32,
+}
+
+impl Transform {
+    fn identity() -> Self {
+        Transform {
+            translation: Vector3::zeros(),
+            rotation: Matrix3::identity(),
+            scale: 1.0,
+        }
+    }
+
+    fn then(&self, translation: Vector3<f32>, rotation: Matrix3<f32>, scale: f32) -> Self {
+        Transform {
+            translation: self.apply(translation),
+            rotation: self.rotation * rotation,
+            scale: self.scale * scale,
+        }
+    }
+
+    fn apply(&self, point: Vector3<f32>) -> Vector3<f32> {
+        self.rotation * (point * self.scale) + self.translation
+    }
+
+    fn apply_normal(&self, normal: Vector3<f32>) -> Vector3<f32> {
+        (self.rotation * normal).normalize()
+    }
+}
+
+/// One flattened NiTriShape, already moved into model space
+struct ObjShape {
+    vertices: Vec<Vector3<f32>>,
+    normals: Vec<Vector3<f32>>,
+    uvs: Vec<[f32; 2]>,
+    triangles: Vec<[u16; 3]>,
+    texture: Option<String>,
+}
+
+pub fn export_models(args: &ArgMatches) {
+    let out_dir = args.get_one::<String>("OUT_DIR").unwrap();
+    let scale_mode = args.get_one::<f32>("SCALE").unwrap_or(&1.0);
+
+    let config =
+        get_config().expect("Openmw.cfg not located! Be sure you have a valid openmw setup.");
+
+    fs::create_dir_all(out_dir).expect("Model output folder creation failed!");
+
+    let mut models: Vec<(String, String)> = Vec::new();
+
+    if let Some(plugin_paths) = args.get_many::<String>("PLUGIN_PATH") {
+        for plugin_path in plugin_paths {
+            let plugin =
+                Plugin::from_path(plugin_path).expect(&format!("Reading {plugin_path} failed!"));
+
+            models.extend(
+                plugin
+                    .objects
+                    .iter()
+                    .filter_map(|object| {
+                        base_object_mesh(object).map(|mesh| (object.editor_id().to_string(), mesh))
+                    })
+                    .filter(|(_, mesh)| !mesh.is_empty()),
+            );
+        }
+    }
+
+    if let Some(mesh_paths) = args.get_many::<String>("MESH_PATH") {
+        for mesh_path in mesh_paths {
+            let name = Path::new(mesh_path)
+                .file_stem()
+                .expect("Mesh paths should always have a file name")
+                .to_string_lossy()
+                .to_string();
+            models.push((name, mesh_path.to_string()));
+        }
+    }
+
+    let mut exported = 0;
+
+    for (name, mesh) in &models {
+        let mesh = mesh.replace('\\', "/");
+        let nif_path = match find_file(&config, format!("Meshes/{mesh}").as_str()) {
+            Ok(path) => path,
+            Err(_) => {
+                eprintln!("ERROR: Mesh {mesh} for {name} is not in your OpenMW VFS, skipping it.");
+                continue;
+            }
+        };
+
+        let stream = match NiStream::from_path(&nif_path) {
+            Ok(stream) => stream,
+            Err(error) => {
+                eprintln!("ERROR: Reading {} failed: {error}", nif_path.display());
+                continue;
+            }
+        };
+
+        let shapes = flatten_shapes(&stream, *scale_mode);
+
+        write_obj(out_dir, name, &shapes, &config);
+        exported += 1;
+    }
+
+    println!(
+        "Exported {exported} of {} models to {out_dir}.",
+        models.len()
+    );
+}
+
+fn base_object_mesh(object: &TES3Object) -> Option<String> {
+    match object {
+        TES3Object::Activator(object) => Some(object.mesh.clone()),
+        TES3Object::Alchemy(object) => Some(object.mesh.clone()),
+        TES3Object::Apparatus(object) => Some(object.mesh.clone()),
+        TES3Object::Armor(object) => Some(object.mesh.clone()),
+        TES3Object::Book(object) => Some(object.mesh.clone()),
+        TES3Object::Clothing(object) => Some(object.mesh.clone()),
+        TES3Object::Container(object) => Some(object.mesh.clone()),
+        TES3Object::Door(object) => Some(object.mesh.clone()),
+        TES3Object::Ingredient(object) => Some(object.mesh.clone()),
+        TES3Object::Light(object) => Some(object.mesh.clone()),
+        TES3Object::Lockpick(object) => Some(object.mesh.clone()),
+        TES3Object::MiscItem(object) => Some(object.mesh.clone()),
+        TES3Object::Probe(object) => Some(object.mesh.clone()),
+        TES3Object::RepairItem(object) => Some(object.mesh.clone()),
+        TES3Object::Static(object) => Some(object.mesh.clone()),
+        TES3Object::Weapon(object) => Some(object.mesh.clone()),
+        _ => None,
+    }
+}
+
+fn flatten_shapes(stream: &NiStream, scale_mode: f32) -> Vec<ObjShape> {
+    let mut shapes = Vec::new();
+
+    // Models are shown in map units, so undo the compile scale
+    let root_transform = Transform {
+        scale: 1.0 / scale_mode,
+        ..Transform::identity()
+    };
+
+    for root in &stream.roots {
+        collect_node_shapes(stream, root.cast(), root_transform, &mut shapes);
+    }
+
+    shapes
+}
+
+/// Every node type which draws its children, as the NiNode it is built on
+fn as_node(stream: &NiStream, link: NiLink<NiNode>) -> Option<&NiNode> {
+    if let Some(node) = stream.get(link) {
+        return Some(node);
+    }
+    if let Some(node) = stream.get(link.cast::<NiBSAnimationNode>()) {
+        return Some(&node.base);
+    }
+    if let Some(node) = stream.get(link.cast::<NiBSParticleNode>()) {
+        return Some(&node.base);
+    }
+    if let Some(node) = stream.get(link.cast::<NiBillboardNode>()) {
+        return Some(&node.base);
+    }
+    if let Some(node) = stream.get(link.cast::<NiSortAdjustNode>()) {
+        return Some(&node.base);
+    }
+    if let Some(node) = stream.get(link.cast::<NiBSPArrayNode>()) {
+        return Some(&node.base);
+    }
+    // Every child of a switch is exported, since the editor can't show which one is active
+    if let Some(node) = stream.get(link.cast::<NiSwitchNode>()) {
+        return Some(&node.base);
+    }
+    None
+}
+
+/// Collision nodes are left out, as they are never drawn
+fn collect_node_shapes(
+    stream: &NiStream,
+    link: NiLink<NiNode>,
+    parent: Transform,
+    shapes: &mut Vec<ObjShape>,
+) {
+    let Some(node) = as_node(stream, link) else {
+        return;
+    };
+
+    if node.flags & 1 != 0 {
+        return; // Hidden
+    }
+
+    let transform = parent.then(
+        Vector3::new(node.translation.x, node.translation.y, node.translation.z),
+        Matrix3::from_column_slice(&node.rotation.to_cols_array()),
+        node.scale,
+    );
+
+    for child in &node.children {
+        if let Some(shape) = stream.get(child.cast::<NiTriShape>()) {
+            if shape.flags & 1 != 0 {
+                continue;
+            }
+
+            let shape_transform = transform.then(
+                Vector3::new(
+                    shape.translation.x,
+                    shape.translation.y,
+                    shape.translation.z,
+                ),
+                Matrix3::from_column_slice(&shape.rotation.to_cols_array()),
+                shape.scale,
+            );
+
+            if let Some(obj_shape) = flatten_shape(stream, shape, shape_transform) {
+                shapes.push(obj_shape);
+            }
+        } else if as_node(stream, child.cast()).is_some() {
+            collect_node_shapes(stream, child.cast(), transform, shapes);
+        } else if stream.get(child.cast::<RootCollisionNode>()).is_none() {
+            println!(
+                "Warning! A child of {} is neither a node nor an NiTriShape, so it was left out of the model.",
+                node.name
+            );
+        }
+    }
+}
+
+fn flatten_shape(stream: &NiStream, shape: &NiTriShape, transform: Transform) -> Option<ObjShape> {
+    let data = stream.get(shape.geometry_data.cast::<NiTriShapeData>())?;
+
+    Some(ObjShape {
+        vertices: data
+            .vertices
+            .iter()
+            .map(|vert| transform.apply(Vector3::new(vert.x, vert.y, vert.z)))
+            .collect(),
+        normals: data
+            .normals
+            .iter()
+            .map(|normal| transform.apply_normal(Vector3::new(normal.x, normal.y, normal.z)))
+            .collect(),
+        uvs: data.uv_sets.iter().map(|uv| [uv.x, uv.y]).collect(),
+        triangles: data.triangles.clone(),
+        texture: shape_texture(stream, shape),
+    })
+}
+
+fn shape_texture(stream: &NiStream, shape: &NiTriShape) -> Option<String> {
+    shape.properties.iter().find_map(|property| {
+        let tex_prop = stream.get(property.cast::<NiTexturingProperty>())?;
+        let Some(Some(TextureMap::Map(base_map))) = tex_prop.texture_maps.first() else {
+            return None;
+        };
+        let texture = stream.get(base_map.texture.cast::<NiSourceTexture>())?;
+        match &texture.source {
+            TextureSource::External(path) => Some(path.to_string()),
+            _ => None,
+        }
+    })
+}
+
+fn write_obj(out_dir: &str, name: &str, shapes: &[ObjShape], config: &Ini) {
+    let mut obj = format!("mtllib {name}.mtl\n");
+    let mut mtl = String::new();
+    // v, vt and vn are numbered separately across the whole file
+    let (mut vertex_offset, mut uv_offset, mut normal_offset) = (1, 1, 1);
+
+    for (index, shape) in shapes.iter().enumerate() {
+        let _ = writeln!(obj, "o shape{index}");
+
+        for vert in &shape.vertices {
+            let _ = writeln!(obj, "v {} {} {}", vert.x, vert.y, vert.z);
+        }
+
+        // Faces index normals and uvs by vertex, so they're only usable with one for each vertex
+        let has_normals = shape.normals.len() == shape.vertices.len();
+        let has_uvs = shape.uvs.len() == shape.vertices.len();
+
+        if has_normals {
+            for normal in &shape.normals {
+                let _ = writeln!(obj, "vn {} {} {}", normal.x, normal.y, normal.z);
+            }
+        }
+        // OBJ textures are addressed from the bottom left, NIF textures from the top left
+        if has_uvs {
+            for uv in &shape.uvs {
+                let _ = writeln!(obj, "vt {} {}", uv[0], 1.0 - uv[1]);
+            }
+        }
+
+        if let Some(texture) = &shape.texture {
+            let texture = texture.replace('\\', "/");
+            let texture = match texture.to_ascii_lowercase().starts_with("textures/") {
+                true => &texture["textures/".len()..],
+                false => texture.as_str(),
+            };
+            let texture_name = Path::new(texture)
+                .with_extension("")
+                .to_string_lossy()
+                .to_string();
+
+            if let Some(texture_path) = MapData::find_vfs_texture(&texture_name, config) {
+                let _ = writeln!(mtl, "newmtl shape{index}\nmap_Kd {texture_path}\n");
+                let _ = writeln!(obj, "usemtl shape{index}");
+            }
+        }
+
+        for triangle in &shape.triangles {
+            let corners: Vec<String> = triangle
+                .iter()
+                .map(|index| {
+                    let index = *index as usize;
+                    let vertex = index + vertex_offset;
+                    let uv = index + uv_offset;
+                    let normal = index + normal_offset;
+                    match (has_uvs, has_normals) {
+                        (true, true) => format!("{vertex}/{uv}/{normal}"),
+                        (true, false) => format!("{vertex}/{uv}"),
+                        (false, true) => format!("{vertex}//{normal}"),
+                        (false, false) => format!("{vertex}"),
+                    }
+                })
+                .collect();
+            let _ = writeln!(obj, "f {}", corners.join(" "));
+        }
+
+        vertex_offset += shape.vertices.len();
+        if has_uvs {
+            uv_offset += shape.uvs.len();
+        }
+        if has_normals {
+            normal_offset += shape.normals.len();
+        }
+    }
+
+    fs::write(format!("{out_dir}/{name}.obj"), obj)
+        .expect(&format!("Saving model for {name} failed!"));
+    fs::write(format!("{out_dir}/{name}.mtl"), mtl)
+        .expect(&format!("Saving materials for {name} failed!"));
+}
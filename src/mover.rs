@@ -0,0 +1,155 @@
+use nalgebra::{Unit, UnitQuaternion, Vector3};
+use std::collections::HashMap;
+
+use crate::get_prop;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MoverKind {
+    Rotating,
+    DoorRotating,
+}
+
+/// Brush entities which animate their base node with an NiKeyframeController.
+/// Rotations are stored in degrees and converted when generating keys.
+#[derive(Debug)]
+pub struct Mover {
+    pub kind: MoverKind,
+    pub axis: Unit<Vector3<f32>>,
+    pub angle: f32,
+    pub speed: f32,
+}
+
+impl Mover {
+    pub fn from_props(classname: &str, entity_props: &HashMap<&String, &String>) -> Option<Mover> {
+        let kind = match classname {
+            "func_rotating" => MoverKind::Rotating,
+            "func_door_rotating" => MoverKind::DoorRotating,
+            _ => return None,
+        };
+
+        let axis = match get_prop("RotationAxis", entity_props).as_str() {
+            "1" => Vector3::x_axis(),
+            "2" => Vector3::y_axis(),
+            _ => Vector3::z_axis(),
+        };
+
+        let angle = match get_prop("Angle", entity_props).parse::<f32>() {
+            Ok(angle) => angle,
+            Err(_) => 90.0,
+        };
+
+        let speed = match get_prop("Speed", entity_props).parse::<f32>() {
+            Ok(speed) if speed != 0.0 => speed,
+            _ => 90.0,
+        };
+
+        Some(Mover {
+            kind,
+            axis,
+            angle,
+            speed,
+        })
+    }
+
+    /// Length in seconds of a single swing or revolution
+    pub fn duration(&self) -> f32 {
+        match self.kind {
+            MoverKind::Rotating => 360.0 / self.speed.abs(),
+            MoverKind::DoorRotating => self.angle.abs() / self.speed.abs(),
+        }
+    }
+
+    pub fn rotation_keys(&self) -> Vec<(f32, UnitQuaternion<f32>)> {
+        let duration = self.duration();
+        let rotation_at =
+            |degrees: f32| UnitQuaternion::from_axis_angle(&self.axis, degrees.to_radians());
+
+        match self.kind {
+            // Linear quaternion keys take the shortest path, so a full turn needs more than two keys
+            MoverKind::Rotating => (0..=4)
+                .map(|step| {
+                    let fraction = step as f32 / 4.0;
+                    (
+                        duration * fraction,
+                        rotation_at(360.0 * fraction * self.speed.signum()),
+                    )
+                })
+                .collect(),
+            MoverKind::DoorRotating => vec![
+                (0.0, rotation_at(0.0)),
+                (duration, rotation_at(self.angle)),
+                (duration * 2.0, rotation_at(0.0)),
+            ],
+        }
+    }
+
+    pub fn text_keys(&self) -> Vec<(f32, String)> {
+        let duration = self.duration();
+
+        match self.kind {
+            MoverKind::Rotating => vec![
+                (0.0, "Idle: Start".to_string()),
+                (duration, "Idle: Stop".to_string()),
+            ],
+            MoverKind::DoorRotating => vec![
+                (0.0, "Idle: Start\r\nIdle: Stop\r\nOpen: Start".to_string()),
+                (
+                    duration,
+                    "Open: Stop\r\nIdle2: Start\r\nIdle2: Stop\r\nClose: Start".to_string(),
+                ),
+                (duration * 2.0, "Close: Stop".to_string()),
+            ],
+        }
+    }
+
+    /// Scripts are written as source only. OpenMW compiles them on load, but
+    /// Morrowind.exe needs them recompiled once in the construction set.
+    pub fn script(&self, script_id: &str, entity_props: &HashMap<&String, &String>) -> String {
+        match self.kind {
+            MoverKind::Rotating => format!(
+                "Begin {script_id}\r\n\
+                 \r\n\
+                 short started\r\n\
+                 \r\n\
+                 if ( started == 0 )\r\n\
+                 \tLoopGroup Idle 1000000 1\r\n\
+                 \tset started to 1\r\n\
+                 endif\r\n\
+                 \r\n\
+                 End {script_id}\r\n"
+            ),
+            MoverKind::DoorRotating => {
+                let open_sound = sound_line(&get_prop("OpenSound", entity_props));
+                let close_sound = sound_line(&get_prop("CloseSound", entity_props));
+
+                format!(
+                    "Begin {script_id}\r\n\
+                     \r\n\
+                     short isOpen\r\n\
+                     \r\n\
+                     if ( OnActivate == 1 )\r\n\
+                     \tif ( isOpen == 0 )\r\n\
+                     {open_sound}\
+                     \t\tPlayGroup Open 1\r\n\
+                     \t\tLoopGroup Idle2 1000000\r\n\
+                     \t\tset isOpen to 1\r\n\
+                     \telse\r\n\
+                     {close_sound}\
+                     \t\tPlayGroup Close 1\r\n\
+                     \t\tset isOpen to 0\r\n\
+                     \tendif\r\n\
+                     endif\r\n\
+                     \r\n\
+                     End {script_id}\r\n"
+                )
+            }
+        }
+    }
+}
+
+fn sound_line(sound: &str) -> String {
+    match sound {
+        "" => String::new(),
+        sound => format!("\t\tPlaySound3D \"{sound}\"\r\n"),
+    }
+}
@@ -0,0 +1,525 @@
+use shambler::Vector3 as SV3;
+use std::collections::{BTreeSet, HashMap};
+use tes3::esp::{PathGrid, PathGridData, PathGridPoint, TES3Object};
+
+use crate::{fgd, get_prop, surfaces, MapData};
+
+/// Lines of sight are tested this far above the floor, so stairs and small ledges don't block them
+const STEP_HEIGHT: f32 = 24.0;
+/// Floors may slope up to roughly 45 degrees
+const MIN_FLOOR_NORMAL_Z: f32 = 0.7;
+const DEFAULT_SPACING: f32 = 128.0;
+
+type Triangle = [SV3; 3];
+
+/// Solid triangles bucketed by the grid columns they cover, so rays only test what is near them
+struct Solids {
+    cell_size: f32,
+    triangles: Vec<Triangle>,
+    columns: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl Solids {
+    fn new(triangles: Vec<Triangle>, cell_size: f32) -> Solids {
+        let mut solids = Solids {
+            cell_size,
+            triangles,
+            columns: HashMap::new(),
+        };
+
+        for (index, [a, b, c]) in solids.triangles.iter().enumerate() {
+            let (min_x, min_y) = solids.column(a.inf(b).inf(c));
+            let (max_x, max_y) = solids.column(a.sup(b).sup(c));
+
+            for column_x in min_x..=max_x {
+                for column_y in min_y..=max_y {
+                    solids
+                        .columns
+                        .entry((column_x, column_y))
+                        .or_default()
+                        .push(index);
+                }
+            }
+        }
+
+        solids
+    }
+
+    fn column(&self, position: SV3) -> (i32, i32) {
+        (
+            (position.x / self.cell_size).floor() as i32,
+            (position.y / self.cell_size).floor() as i32,
+        )
+    }
+
+    /// Triangles in the columns between two points, each listed once
+    fn near(&self, start: SV3, end: SV3) -> Vec<&Triangle> {
+        let (min_x, min_y) = self.column(start.inf(&end));
+        let (max_x, max_y) = self.column(start.sup(&end));
+
+        let indices: BTreeSet<usize> = (min_x..=max_x)
+            .flat_map(|column_x| (min_y..=max_y).map(move |column_y| (column_x, column_y)))
+            .filter_map(|column| self.columns.get(&column))
+            .flatten()
+            .copied()
+            .collect();
+
+        indices
+            .into_iter()
+            .map(|index| &self.triangles[index])
+            .collect()
+    }
+
+    /// Distance to the nearest solid straight above the position
+    fn ceiling(&self, position: SV3) -> Option<f32> {
+        self.near(position, position)
+            .into_iter()
+            .filter_map(|triangle| ray_hit(position, SV3::z(), triangle))
+            .reduce(f32::min)
+    }
+}
+
+struct Node {
+    position: SV3,
+    auto_generated: bool,
+}
+
+/// Samples walkable floors on a grid and joins neighbouring samples which can see each other.
+/// `info_pathnode` entities are added on top and linked through their `target` keys.
+pub fn generate(map_data: &MapData, cell_name: &str, scale_mode: &f32) -> Option<TES3Object> {
+    let worldspawn = map_data
+        .geomap
+        .entity_brushes
+        .keys()
+        .map(|entity_id| map_data.get_entity_properties(entity_id))
+        .find(|props| get_prop("classname", props) == "worldspawn")
+        .unwrap_or_default();
+
+    let (use_auto, use_manual) = match get_prop("PathGrid", &worldspawn).as_str() {
+        "1" => (false, true),
+        "2" => (false, false),
+        _ => (true, true),
+    };
+
+    let spacing = match get_prop("PathGridSpacing", &worldspawn).parse::<f32>() {
+        Ok(spacing) if spacing > 0.0 => spacing,
+        _ => DEFAULT_SPACING,
+    };
+
+    // Nodes need room for the player's hull, as trenchbroom draws it for info_player_start
+    let player_height = fgd::hull(&fgd::classes(), "info_player_start")
+        .map(|(mins, maxs)| maxs[2] - mins[2])
+        .expect("info_player_start should always have a size in the fgd");
+
+    let (solids, floors) = collect_triangles(map_data);
+    let solids = Solids::new(solids, spacing);
+
+    let mut nodes = Vec::new();
+    let mut links: Vec<BTreeSet<usize>> = Vec::new();
+
+    if use_auto {
+        for position in sample_floors(&floors, &solids, spacing, player_height) {
+            nodes.push(Node {
+                position,
+                auto_generated: true,
+            });
+        }
+        links = link_neighbours(&nodes, &solids, spacing);
+    }
+
+    if use_manual {
+        add_manual_nodes(map_data, &solids, spacing, &mut nodes, &mut links);
+    }
+
+    if nodes.is_empty() {
+        return None;
+    }
+
+    if nodes.len() > u16::MAX as usize {
+        println!(
+            "Warning! {cell_name} has {} path grid nodes, but only {} fit in a path grid. Increase PathGridSpacing.",
+            nodes.len(),
+            u16::MAX
+        );
+        return None;
+    }
+
+    println!(
+        "Generated path grid for {cell_name} with {} nodes",
+        nodes.len()
+    );
+
+    Some(TES3Object::PathGrid(PathGrid {
+        cell: cell_name.to_string(),
+        data: PathGridData {
+            granularity: (spacing * scale_mode).round() as u16,
+            num_points: nodes.len() as u16,
+            ..Default::default()
+        },
+        points: nodes
+            .iter()
+            .zip(&links)
+            .map(|(node, node_links)| {
+                let position = node.position * *scale_mode;
+                PathGridPoint {
+                    location: [
+                        position.x.round() as i32,
+                        position.y.round() as i32,
+                        position.z.round() as i32,
+                    ],
+                    auto_generated: node.auto_generated as u8,
+                    connection_count: node_links.len().min(u8::MAX as usize) as u8,
+                }
+            })
+            .collect(),
+        connections: links
+            .iter()
+            .flat_map(|node_links| node_links.iter().take(u8::MAX as usize))
+            .map(|index| *index as u32)
+            .collect(),
+        ..Default::default()
+    }))
+}
+
+/// Every triangle which blocks movement, and the subset of those which can be stood on
+fn collect_triangles(map_data: &MapData) -> (Vec<Triangle>, Vec<Triangle>) {
+    let mut solids = Vec::new();
+    let mut floors = Vec::new();
+
+    for (entity_id, brushes) in map_data.brush_entities() {
+        // Triggers, scatters and delete volumes are volumes and illusionary brushes can be walked through, so none are obstacles
+        let classname = get_prop("classname", &map_data.get_entity_properties(entity_id));
+        if classname.starts_with("trigger_")
+            || matches!(
+                classname.as_str(),
+                "func_illusionary" | "func_scatter" | "world_DeleteVolume"
+            )
+        {
+            continue;
+        }
+
+        for brush_id in brushes {
+            if map_data.brush_is_origin(brush_id) {
+                continue;
+            }
+
+            for face_id in map_data
+                .geomap
+                .brush_faces
+                .get(brush_id)
+                .expect("Brush faces should always be valid")
+            {
+                let texture_id = map_data.geomap.face_textures.get(face_id).unwrap();
+                let texture_name = map_data.geomap.textures.get(texture_id).unwrap();
+
+                if surfaces::is_liquid(texture_name) {
+                    continue;
+                }
+
+                let vertices = map_data.face_vertices.get(face_id).unwrap();
+                let indices = map_data.face_tri_indices.get(face_id).unwrap();
+                let is_floor = map_data
+                    .flat_normals
+                    .get(face_id)
+                    .and_then(|normals| normals.first())
+                    .is_some_and(|normal| normal.z >= MIN_FLOOR_NORMAL_Z);
+
+                for triangle in indices.chunks_exact(3) {
+                    let triangle = [
+                        vertices[triangle[0]],
+                        vertices[triangle[1]],
+                        vertices[triangle[2]],
+                    ];
+
+                    solids.push(triangle);
+                    if is_floor {
+                        floors.push(triangle);
+                    }
+                }
+            }
+        }
+    }
+
+    (solids, floors)
+}
+
+/// Floor positions on the spacing grid which have room for the player to stand
+fn sample_floors(
+    floors: &[Triangle],
+    solids: &Solids,
+    spacing: f32,
+    player_height: f32,
+) -> Vec<SV3> {
+    let mut samples: HashMap<[i32; 3], SV3> = HashMap::new();
+
+    for [a, b, c] in floors {
+        let min_x = (a.x.min(b.x).min(c.x) / spacing).ceil() as i32;
+        let max_x = (a.x.max(b.x).max(c.x) / spacing).floor() as i32;
+        let min_y = (a.y.min(b.y).min(c.y) / spacing).ceil() as i32;
+        let max_y = (a.y.max(b.y).max(c.y) / spacing).floor() as i32;
+
+        for grid_x in min_x..=max_x {
+            for grid_y in min_y..=max_y {
+                let (x, y) = (grid_x as f32 * spacing, grid_y as f32 * spacing);
+
+                let Some(z) = height_on_triangle(x, y, a, b, c) else {
+                    continue;
+                };
+
+                let position = SV3::new(x, y, z);
+                let ceiling = solids.ceiling(position + SV3::z() * 0.5);
+
+                // Floors with nothing above them are the outside of the map's shell
+                let has_headroom = ceiling.is_some_and(|distance| distance > player_height);
+
+                // Coplanar faces from neighbouring brushes sample the same spot
+                if has_headroom {
+                    samples
+                        .entry([grid_x, grid_y, (z / 8.0).round() as i32])
+                        .or_insert(position);
+                }
+            }
+        }
+    }
+
+    // Sorted so that node indices are the same on every compile
+    let mut samples: Vec<([i32; 3], SV3)> = samples.into_iter().collect();
+    samples.sort_by_key(|(key, _)| *key);
+    samples.into_iter().map(|(_, position)| position).collect()
+}
+
+/// Joins each node to the surrounding grid cells it can reach
+fn link_neighbours(nodes: &[Node], solids: &Solids, spacing: f32) -> Vec<BTreeSet<usize>> {
+    let mut columns: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+
+    for (index, node) in nodes.iter().enumerate() {
+        columns
+            .entry(grid_column(node.position, spacing))
+            .or_default()
+            .push(index);
+    }
+
+    nodes
+        .iter()
+        .enumerate()
+        .map(|(index, node)| {
+            let (column_x, column_y) = grid_column(node.position, spacing);
+
+            (-1..=1)
+                .flat_map(|offset_x| (-1..=1).map(move |offset_y| (offset_x, offset_y)))
+                .filter_map(|(offset_x, offset_y)| {
+                    columns.get(&(column_x + offset_x, column_y + offset_y))
+                })
+                .flatten()
+                .copied()
+                .filter(|other| {
+                    *other != index
+                        && can_walk_between(node.position, nodes[*other].position, solids)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn add_manual_nodes(
+    map_data: &MapData,
+    solids: &Solids,
+    spacing: f32,
+    nodes: &mut Vec<Node>,
+    links: &mut Vec<BTreeSet<usize>>,
+) {
+    let auto_count = nodes.len();
+    let mut names: HashMap<String, usize> = HashMap::new();
+    let mut targets: Vec<(usize, String, String)> = Vec::new();
+
+    for entity_id in map_data.point_entities() {
+        let props = map_data.get_entity_properties(entity_id);
+
+        if get_prop("classname", &props) != "info_pathnode" {
+            continue;
+        }
+
+        let position = crate::parse_origin(&get_prop("origin", &props));
+        let index = nodes.len();
+
+        nodes.push(Node {
+            position,
+            auto_generated: false,
+        });
+
+        // Manual nodes join the generated grid wherever it is close by and visible
+        links.push(
+            (0..auto_count)
+                .filter(|other| {
+                    let offset = nodes[*other].position - position;
+                    offset.x.abs() <= spacing
+                        && offset.y.abs() <= spacing
+                        && can_walk_between(position, nodes[*other].position, solids)
+                })
+                .collect(),
+        );
+        for other in links[index].clone() {
+            links[other].insert(index);
+        }
+
+        let name = get_prop("targetname", &props);
+        if !name.is_empty() && names.insert(name.clone(), index).is_some() {
+            println!("Warning! More than one info_pathnode is named {name}. Links to it use the last one.");
+        }
+
+        for target in get_prop("target", &props).split_whitespace() {
+            targets.push((index, target.to_string(), entity_id.to_string()));
+        }
+    }
+
+    for (index, target, entity_id) in targets {
+        match names.get(&target) {
+            Some(&other) if other != index => {
+                links[index].insert(other);
+                links[other].insert(index);
+            }
+            Some(_) => {}
+            None => println!(
+                "Warning! info_pathnode {entity_id} targets {target}, but no info_pathnode has that targetname."
+            ),
+        }
+    }
+}
+
+fn grid_column(position: SV3, spacing: f32) -> (i32, i32) {
+    (
+        (position.x / spacing).round() as i32,
+        (position.y / spacing).round() as i32,
+    )
+}
+
+fn can_walk_between(start: SV3, end: SV3, solids: &Solids) -> bool {
+    let offset = end - start;
+    let flat_distance = (offset.x * offset.x + offset.y * offset.y).sqrt();
+
+    if offset.z.abs() > flat_distance.max(STEP_HEIGHT) {
+        return false;
+    }
+
+    let eye = start + SV3::z() * STEP_HEIGHT;
+    let length = offset.norm();
+    let direction = offset / length;
+
+    solids.near(start, end).into_iter().all(|triangle| {
+        ray_hit(eye, direction, triangle).map_or(true, |distance| distance >= length)
+    })
+}
+
+/// Height of the triangle at the given point, if the point is over it
+fn height_on_triangle(x: f32, y: f32, a: &SV3, b: &SV3, c: &SV3) -> Option<f32> {
+    let denominator = (b.y - c.y) * (a.x - c.x) + (c.x - b.x) * (a.y - c.y);
+
+    if denominator.abs() < f32::EPSILON {
+        return None;
+    }
+
+    let weight_a = ((b.y - c.y) * (x - c.x) + (c.x - b.x) * (y - c.y)) / denominator;
+    let weight_b = ((c.y - a.y) * (x - c.x) + (a.x - c.x) * (y - c.y)) / denominator;
+    let weight_c = 1.0 - weight_a - weight_b;
+
+    let tolerance = -1e-4;
+    if weight_a < tolerance || weight_b < tolerance || weight_c < tolerance {
+        return None;
+    }
+
+    Some(weight_a * a.z + weight_b * b.z + weight_c * c.z)
+}
+
+/// Möller–Trumbore intersection, returning the distance along the ray
+fn ray_hit(origin: SV3, direction: SV3, [a, b, c]: &Triangle) -> Option<f32> {
+    let edge_1 = b - a;
+    let edge_2 = c - a;
+    let p = direction.cross(&edge_2);
+    let determinant = edge_1.dot(&p);
+
+    if determinant.abs() < f32::EPSILON {
+        return None;
+    }
+
+    let to_origin = origin - a;
+    let u = to_origin.dot(&p) / determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = to_origin.cross(&edge_1);
+    let v = direction.dot(&q) / determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let distance = edge_2.dot(&q) / determinant;
+    (distance > 0.0).then_some(distance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn floor() -> Triangle {
+        [
+            SV3::new(0.0, 0.0, 0.0),
+            SV3::new(64.0, 0.0, 32.0),
+            SV3::new(0.0, 64.0, 0.0),
+        ]
+    }
+
+    fn wall() -> Triangle {
+        [
+            SV3::new(32.0, -64.0, -64.0),
+            SV3::new(32.0, 64.0, -64.0),
+            SV3::new(32.0, 0.0, 128.0),
+        ]
+    }
+
+    #[test]
+    fn height_is_interpolated_across_the_triangle() {
+        let [a, b, c] = floor();
+        assert_eq!(height_on_triangle(0.0, 0.0, &a, &b, &c), Some(0.0));
+        assert_eq!(height_on_triangle(32.0, 16.0, &a, &b, &c), Some(16.0));
+        assert_eq!(height_on_triangle(64.0, 64.0, &a, &b, &c), None);
+    }
+
+    #[test]
+    fn vertical_triangles_have_no_height() {
+        let [a, b, c] = wall();
+        assert_eq!(height_on_triangle(32.0, 0.0, &a, &b, &c), None);
+    }
+
+    #[test]
+    fn rays_hit_triangles_in_front_of_them() {
+        let origin = SV3::new(0.0, 0.0, 0.0);
+        assert_eq!(ray_hit(origin, SV3::x(), &wall()), Some(32.0));
+        assert_eq!(ray_hit(origin, -SV3::x(), &wall()), None);
+        assert_eq!(ray_hit(origin, SV3::y(), &wall()), None);
+        assert_eq!(ray_hit(SV3::new(0.0, 0.0, 200.0), SV3::x(), &wall()), None);
+    }
+
+    #[test]
+    fn walls_block_walking_through_them() {
+        let solids = Solids::new(vec![wall()], 64.0);
+        let start = SV3::new(0.0, 0.0, 0.0);
+
+        assert!(!can_walk_between(start, SV3::new(64.0, 0.0, 0.0), &solids));
+        assert!(can_walk_between(start, SV3::new(0.0, 64.0, 0.0), &solids));
+        assert!(can_walk_between(start, SV3::new(-64.0, 0.0, 0.0), &solids));
+    }
+
+    #[test]
+    fn ceilings_are_found_in_any_column_the_triangle_covers() {
+        let roof = [
+            SV3::new(-100.0, -100.0, 96.0),
+            SV3::new(300.0, -100.0, 96.0),
+            SV3::new(-100.0, 300.0, 96.0),
+        ];
+        let solids = Solids::new(vec![roof], 64.0);
+
+        assert_eq!(solids.ceiling(SV3::new(150.0, 20.0, 0.0)), Some(96.0));
+        assert_eq!(solids.ceiling(SV3::new(250.0, 250.0, 0.0)), None);
+    }
+}
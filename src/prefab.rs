@@ -0,0 +1,279 @@
+use nalgebra::{Rotation3, Vector3};
+use shambler::Vector3 as SV3;
+use std::{
+    cmp::min,
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+use tes3::esp::{Static, TES3Object};
+
+use crate::{
+    get_prop, get_rotation, is_world_class, manifest::fnv1a, parse_origin, point_entity_position,
+    MapData, Mesh,
+};
+
+/// One reference a prefab places, relative to the prefab's own origin until an instance moves it
+#[derive(Clone)]
+pub struct PrefabPart {
+    /// Tells the parts of one instance apart, so each keeps its reference number
+    pub key: String,
+    pub ref_id: String,
+    pub position: SV3,
+    /// Radians, in the same order as `get_rotation` returns them
+    pub rotation: [f32; 3],
+    pub scale: f32,
+}
+
+/// Maps placed by `misc_prefab` entities. Each prefab is compiled once, however many times
+/// it is placed, and prefabs inside prefabs are flattened into their parent.
+pub struct Prefabs {
+    workdir: String,
+    map_dir: String,
+    scale_mode: f32,
+    /// Folder of the map being compiled, which prefab ids are made relative to
+    root_dir: PathBuf,
+    compiled: HashMap<PathBuf, Vec<PrefabPart>>,
+    /// Base records for prefab brushes, which main adds to the plugin
+    pub created_objects: Vec<TES3Object>,
+    pub mesh_paths: Vec<String>,
+}
+
+impl Prefabs {
+    pub fn new(workdir: &str, map_dir: &str, scale_mode: &f32) -> Prefabs {
+        Prefabs {
+            workdir: workdir.to_string(),
+            map_dir: map_dir.to_string(),
+            scale_mode: *scale_mode,
+            root_dir: PathBuf::new(),
+            compiled: HashMap::new(),
+            created_objects: Vec::new(),
+            mesh_paths: Vec::new(),
+        }
+    }
+
+    /// Every reference placed by one `misc_prefab`, moved to the entity's position,
+    /// rotation and scale. The prefab path is relative to the map which places it.
+    pub fn instance(
+        &mut self,
+        map_name: &str,
+        entity_props: &HashMap<&String, &String>,
+    ) -> Vec<PrefabPart> {
+        let map_path = canonical(Path::new(map_name));
+        self.root_dir = map_path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        let prefab_path = prefab_path(&map_path, entity_props);
+        let transform = Transform::from_props(entity_props, &self.scale_mode);
+
+        self.compile(&prefab_path, &mut vec![map_path])
+            .into_iter()
+            .map(|part| transform.apply(part))
+            .collect()
+    }
+
+    /// `stack` holds the maps which are placing this one, so a prefab which places itself,
+    /// even through other prefabs, is caught instead of recursing forever
+    fn compile(&mut self, path: &Path, stack: &mut Vec<PathBuf>) -> Vec<PrefabPart> {
+        let path = canonical(path);
+
+        if stack.contains(&path) {
+            let chain: Vec<String> = stack
+                .iter()
+                .chain([&path])
+                .map(|path| path.display().to_string())
+                .collect();
+            panic!("Prefabs place each other in a loop! {}", chain.join(" -> "));
+        }
+
+        if let Some(parts) = self.compiled.get(&path) {
+            return parts.clone();
+        }
+
+        stack.push(path.clone());
+
+        let prefab_name = path
+            .file_stem()
+            .expect("Prefab paths should always have a file name")
+            .to_string_lossy()
+            .to_string();
+
+        // Prefabs in different folders may share a file name, so their ids include a hash of where they are
+        let relative_path = path
+            .strip_prefix(&self.root_dir)
+            .unwrap_or(path.as_path())
+            .to_string_lossy()
+            .replace('\\', "/");
+        let path_hash = fnv1a(relative_path.as_bytes()) as u32;
+
+        let mut map_data = MapData::new(&path.to_string_lossy().to_string());
+        map_data.exclude_layers(false);
+
+        let mut parts = Vec::new();
+
+        for (entity_id, brushes) in map_data.brush_entities() {
+            let prop_map = map_data.get_entity_properties(entity_id);
+            let classname = get_prop("classname", &prop_map);
+
+            if classname.starts_with("trigger_") || classname == "world_DeleteVolume" {
+                println!("Warning! {classname} {entity_id} in prefab {prefab_name} is not supported inside prefabs and was left out.");
+                continue;
+            }
+
+            if !is_world_class(&classname)
+                && !matches!(
+                    classname.as_str(),
+                    "func_wall" | "func_illusionary" | "world_Detail"
+                )
+            {
+                println!("Warning! {classname} {entity_id} in prefab {prefab_name} is placed as static geometry, since only static brushes are supported inside prefabs.");
+            }
+
+            // Every brush entity in a prefab becomes static geometry
+            let mut mesh = Mesh::from_map(brushes, &map_data, &self.scale_mode, entity_id);
+            if mesh.node_distances.is_empty() {
+                continue;
+            }
+
+            if let Some(origin) = prop_map.get(&"origin".to_string()) {
+                mesh.pivot = Some(parse_origin(origin));
+            }
+
+            let suffix = format!("-{path_hash:08x}-{entity_id}");
+            let ref_id = format!(
+                "{}{suffix}",
+                &prefab_name[..min(prefab_name.len(), 32usize.saturating_sub(suffix.len()))]
+            );
+            let mesh_name = format!("{}/{ref_id}.nif", self.map_dir);
+            let position = mesh.origin() * self.scale_mode;
+
+            mesh.game_object = TES3Object::Static(Static {
+                id: ref_id.clone(),
+                mesh: mesh_name.clone(),
+                ..Default::default()
+            });
+
+            let mesh_path = format!("{}/Meshes/{mesh_name}", self.workdir);
+            println!("Saving prefab mesh for {ref_id} as {mesh_path}");
+            mesh.save(&mesh_path);
+            self.mesh_paths.push(mesh_path);
+            self.created_objects.push(mesh.game_object.clone());
+
+            parts.push(PrefabPart {
+                key: format!("{path_hash:08x}:{entity_id}"),
+                ref_id,
+                position,
+                rotation: [0.0; 3],
+                scale: 1.0,
+            });
+        }
+
+        for entity_id in map_data.point_entities() {
+            let prop_map = map_data.get_entity_properties(entity_id);
+
+            match get_prop("classname", &prop_map).as_str() {
+                "world_Reference" => {
+                    let ref_id = match prop_map.get(&"RefId".to_string()) {
+                        Some(ref_id) => ref_id[..min(ref_id.len(), 32)].to_string(),
+                        None => panic!(
+                            "RefIds are mandatory for all point entities, failed on reference in prefab {prefab_name}, entity ID: {entity_id}"
+                        ),
+                    };
+
+                    parts.push(PrefabPart {
+                        key: format!("{path_hash:08x}:{entity_id}"),
+                        ref_id,
+                        position: point_entity_position(&self.scale_mode, &prop_map),
+                        rotation: *get_rotation(&get_prop("mangle", &prop_map)),
+                        scale: get_prop("Scale", &prop_map).parse::<f32>().unwrap_or(1.0),
+                    });
+                }
+                "misc_prefab" => {
+                    let nested_path = prefab_path(&path, &prop_map);
+                    let transform = Transform::from_props(&prop_map, &self.scale_mode);
+
+                    parts.extend(self.compile(&nested_path, stack).into_iter().map(|part| {
+                        let mut part = transform.apply(part);
+                        part.key = format!("{path_hash:08x}:{entity_id}/{}", part.key);
+                        part
+                    }));
+                }
+                "info_player_start" | "info_pathnode" | "info_master_anchor" => {}
+                class => {
+                    println!("Warning! {class} {entity_id} in prefab {prefab_name} is not supported inside prefabs and was left out.");
+                }
+            }
+        }
+
+        stack.pop();
+        self.compiled.insert(path, parts.clone());
+
+        parts
+    }
+}
+
+struct Transform {
+    position: SV3,
+    rotation: Rotation3<f32>,
+    scale: f32,
+}
+
+impl Transform {
+    fn from_props(entity_props: &HashMap<&String, &String>, scale_mode: &f32) -> Transform {
+        Transform {
+            position: point_entity_position(scale_mode, entity_props),
+            rotation: rotation_matrix(&get_rotation(&get_prop("mangle", entity_props))),
+            scale: get_prop("Scale", entity_props)
+                .parse::<f32>()
+                .unwrap_or(1.0),
+        }
+    }
+
+    fn apply(&self, part: PrefabPart) -> PrefabPart {
+        PrefabPart {
+            position: self.position + self.rotation * (part.position * self.scale),
+            rotation: rotation_angles(&(self.rotation * rotation_matrix(&part.rotation))),
+            scale: part.scale * self.scale,
+            ..part
+        }
+    }
+}
+
+/// The game turns references about Z, then Y, then X, by the negated angles references store.
+/// Rotations from `get_rotation` are those angles before `insert_reference` negates them.
+fn rotation_matrix(rotation: &[f32; 3]) -> Rotation3<f32> {
+    Rotation3::from_axis_angle(&Vector3::x_axis(), rotation[0])
+        * Rotation3::from_axis_angle(&Vector3::y_axis(), rotation[1])
+        * Rotation3::from_axis_angle(&Vector3::z_axis(), rotation[2])
+}
+
+/// Inverse of `rotation_matrix`
+fn rotation_angles(rotation: &Rotation3<f32>) -> [f32; 3] {
+    let matrix = rotation.matrix();
+    let y = matrix[(0, 2)].clamp(-1.0, 1.0).asin();
+
+    // Pointing straight up or down, X and Z turn about the same axis, so Z is left at zero
+    if matrix[(0, 2)].abs() > 0.9999 {
+        return [matrix[(2, 1)].atan2(matrix[(1, 1)]), y, 0.0];
+    }
+
+    [
+        (-matrix[(1, 2)]).atan2(matrix[(2, 2)]),
+        y,
+        (-matrix[(0, 1)]).atan2(matrix[(0, 0)]),
+    ]
+}
+
+fn prefab_path(placed_in: &Path, entity_props: &HashMap<&String, &String>) -> PathBuf {
+    let prefab = get_prop("Prefab", entity_props);
+    if prefab.is_empty() {
+        panic!(
+            "misc_prefab in {} has no Prefab map set!",
+            placed_in.display()
+        );
+    }
+
+    placed_in.parent().unwrap_or(Path::new(".")).join(prefab)
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).expect(&format!("Prefab {} does not exist!", path.display()))
+}
@@ -0,0 +1,282 @@
+use shambler::entity::EntityId;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+use tes3::esp::Cell;
+
+use crate::MapData;
+
+/// Reference numbers handed out on earlier compiles, so placed objects keep their
+/// refr_index for saved games and scripts. Stored beside the map as `<map>.refs`.
+pub struct RefNumbers {
+    path: PathBuf,
+    assigned: BTreeMap<String, u32>,
+    keys: HashMap<EntityId, String>,
+    /// Keys which come from the entity's place in the map rather than from its properties
+    order_keys: HashSet<String>,
+    order_keyed: HashSet<EntityId>,
+    /// Numbers in use anywhere in the plugin, including those handed out on this compile
+    used: HashSet<u32>,
+    /// Numbers from earlier compiles, which new entities don't take
+    remembered: HashSet<u32>,
+    seen: HashSet<String>,
+}
+
+impl RefNumbers {
+    /// `used` holds numbers taken by other cells in the plugin, which are never handed out
+    pub fn load(map_name: &str, map_data: &MapData, used: HashSet<u32>) -> RefNumbers {
+        let path = Path::new(map_name).with_extension("refs");
+
+        let assigned: BTreeMap<String, u32> = match fs::read_to_string(&path) {
+            Ok(contents) => contents
+                .lines()
+                .filter_map(|line| {
+                    let (key, index) = line.rsplit_once('\t')?;
+                    Some((key.to_string(), index.trim().parse::<u32>().ok()?))
+                })
+                .collect(),
+            Err(_) => BTreeMap::new(),
+        };
+
+        let (keys, order_keys) = entity_keys(map_data);
+
+        RefNumbers {
+            path,
+            remembered: assigned.values().copied().collect(),
+            assigned,
+            keys,
+            order_keys,
+            order_keyed: HashSet::new(),
+            used,
+            seen: HashSet::new(),
+        }
+    }
+
+    /// The number this entity had last time, unless something in the plugin already took it
+    pub fn next_index(&mut self, entity_id: &EntityId, cell: &Cell) -> u32 {
+        let key = self.entity_key(entity_id);
+        self.index_for(key, cell)
+    }
+
+    /// For entities which place several references, such as prefabs. `part` tells them apart.
+    pub fn next_part_index(&mut self, entity_id: &EntityId, part: &str, cell: &Cell) -> u32 {
+        let key = part_key(&self.entity_key(entity_id), part);
+        self.index_for(key, cell)
+    }
+
+    fn entity_key(&mut self, entity_id: &EntityId) -> String {
+        let key = self
+            .keys
+            .get(entity_id)
+            .expect("Every entity has a key")
+            .clone();
+
+        if self.order_keys.contains(&key) {
+            self.order_keyed.insert(*entity_id);
+        }
+
+        key
+    }
+
+    fn index_for(&mut self, key: String, cell: &Cell) -> u32 {
+        let first_use = self.seen.insert(key.clone());
+
+        if first_use {
+            if let Some(&index) = self.assigned.get(&key) {
+                if !self.used.contains(&index) && !cell.references.contains_key(&(0, index)) {
+                    self.used.insert(index);
+                    return index;
+                }
+            }
+        }
+
+        // Numbers remembered for other entities are skipped too, so they can keep them
+        let index = (1..)
+            .find(|n| !self.used.contains(n) && !self.remembered.contains(n))
+            .unwrap_or(1);
+        self.used.insert(index);
+
+        // Entities which share a key can't all keep a number, so only the first one does
+        if first_use {
+            self.assigned.insert(key, index);
+        }
+
+        index
+    }
+
+    /// Entities which weren't in this compile keep their numbers unless `prune` is set
+    pub fn save(&mut self, prune: bool) {
+        if !self.order_keyed.is_empty() {
+            println!(
+                "Warning! {} entities have no _mb_uid or unique RefId, so their reference numbers follow their order in the map. \
+                 Adding or removing entities before them renumbers them. Give an entity a unique _mb_uid to pin its number.",
+                self.order_keyed.len()
+            );
+        }
+
+        if prune {
+            let removed = self.assigned.len();
+            self.assigned.retain(|key, _| self.seen.contains(key));
+            println!(
+                "Freed {} reference numbers from removed entities",
+                removed - self.assigned.len()
+            );
+        }
+
+        let mut contents = String::from("# Stable reference numbers, maintained by morrobroom\n");
+        for (key, index) in &self.assigned {
+            let _ = writeln!(contents, "{key}\t{index}");
+        }
+
+        fs::write(&self.path, contents).expect(&format!("Saving {} failed!", self.path.display()));
+    }
+}
+
+fn part_key(entity_key: &str, part: &str) -> String {
+    format!("{entity_key}/{part}")
+}
+
+/// Entities are keyed by `_mb_uid` when set, then by RefId, then by their index in the map.
+/// Entities sharing a RefId are told apart by their order in the map.
+/// Those order-based keys are returned as well, since they change when entities are added or removed.
+fn entity_keys(map_data: &MapData) -> (HashMap<EntityId, String>, HashSet<String>) {
+    let mut entity_ids: Vec<&EntityId> = map_data.geomap.entity_properties.keys().collect();
+    entity_ids.sort();
+
+    let mut ref_id_counts: HashMap<String, u32> = HashMap::new();
+    let mut order_keys = HashSet::new();
+
+    let keys: HashMap<EntityId, String> = entity_ids
+        .into_iter()
+        .map(|entity_id| {
+            let props = map_data.get_entity_properties(entity_id);
+
+            let key = match (
+                props.get(&"_mb_uid".to_string()),
+                props.get(&"RefId".to_string()),
+            ) {
+                (Some(uid), _) => format!("uid:{uid}"),
+                (None, Some(ref_id)) => {
+                    let count = ref_id_counts.entry(ref_id.to_string()).or_insert(0);
+                    *count += 1;
+                    format!("ref:{ref_id}#{count}")
+                }
+                (None, None) => {
+                    let key = format!("entity:{entity_id}");
+                    order_keys.insert(key.clone());
+                    key
+                }
+            };
+
+            (*entity_id, key)
+        })
+        .collect();
+
+    for (ref_id, count) in ref_id_counts {
+        if count > 1 {
+            order_keys.extend((1..=count).map(|n| format!("ref:{ref_id}#{n}")));
+        }
+    }
+
+    (keys, order_keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tes3::esp::Reference;
+
+    fn ref_numbers(assigned: &[(&str, u32)], used: &[u32]) -> RefNumbers {
+        let assigned: BTreeMap<String, u32> = assigned
+            .iter()
+            .map(|(key, index)| (key.to_string(), *index))
+            .collect();
+
+        RefNumbers {
+            path: PathBuf::new(),
+            remembered: assigned.values().copied().collect(),
+            assigned,
+            keys: HashMap::new(),
+            order_keys: HashSet::new(),
+            order_keyed: HashSet::new(),
+            used: used.iter().copied().collect(),
+            seen: HashSet::new(),
+        }
+    }
+
+    #[test]
+    fn remembered_numbers_are_reused() {
+        let mut numbers = ref_numbers(&[("ref:a#1", 5), ("ref:b#1", 2)], &[]);
+        let cell = Cell::default();
+
+        assert_eq!(numbers.index_for("ref:b#1".to_string(), &cell), 2);
+        assert_eq!(numbers.index_for("ref:a#1".to_string(), &cell), 5);
+    }
+
+    #[test]
+    fn new_entities_skip_remembered_numbers() {
+        let mut numbers = ref_numbers(&[("ref:a#1", 1), ("ref:b#1", 3)], &[]);
+        let cell = Cell::default();
+
+        assert_eq!(numbers.index_for("ref:c#1".to_string(), &cell), 2);
+        assert_eq!(numbers.index_for("ref:d#1".to_string(), &cell), 4);
+        assert_eq!(numbers.index_for("ref:a#1".to_string(), &cell), 1);
+        assert_eq!(numbers.index_for("ref:b#1".to_string(), &cell), 3);
+    }
+
+    #[test]
+    fn numbers_taken_elsewhere_in_the_plugin_are_replaced() {
+        let mut numbers = ref_numbers(&[("ref:a#1", 2)], &[1, 2]);
+        let cell = Cell::default();
+
+        assert_eq!(numbers.index_for("ref:a#1".to_string(), &cell), 3);
+        assert_eq!(numbers.assigned.get("ref:a#1"), Some(&3));
+    }
+
+    #[test]
+    fn numbers_taken_in_the_cell_are_replaced() {
+        let mut numbers = ref_numbers(&[("ref:a#1", 1)], &[]);
+        let mut cell = Cell::default();
+        cell.references.insert((0, 1), Reference::default());
+
+        assert_eq!(numbers.index_for("ref:a#1".to_string(), &cell), 2);
+    }
+
+    #[test]
+    fn parts_of_one_entity_keep_their_own_numbers() {
+        let mut numbers = ref_numbers(
+            &[
+                (part_key("uid:prefab", "house:1").as_str(), 7),
+                (part_key("uid:prefab", "house:2").as_str(), 3),
+            ],
+            &[],
+        );
+        let cell = Cell::default();
+
+        assert_eq!(
+            numbers.index_for(part_key("uid:prefab", "house:2"), &cell),
+            3
+        );
+        assert_eq!(
+            numbers.index_for(part_key("uid:prefab", "house:1"), &cell),
+            7
+        );
+        assert_eq!(
+            numbers.index_for(part_key("uid:prefab", "house:3"), &cell),
+            1
+        );
+    }
+
+    #[test]
+    fn shared_keys_only_keep_the_first_number() {
+        let mut numbers = ref_numbers(&[("ref:a#1", 4)], &[]);
+        let cell = Cell::default();
+
+        assert_eq!(numbers.index_for("ref:a#1".to_string(), &cell), 4);
+        assert_eq!(numbers.index_for("ref:a#1".to_string(), &cell), 1);
+        assert_eq!(numbers.assigned.get("ref:a#1"), Some(&4));
+    }
+}
@@ -0,0 +1,203 @@
+use shambler::{brush::BrushId, entity::EntityId, Vector3 as SV3};
+use std::{cmp::min, f32::consts::TAU};
+
+use crate::{get_prop, is_world_class, manifest::fnv1a, MapData};
+
+/// Surfaces steeper than this are never scattered on
+const MIN_SURFACE_NORMAL_Z: f32 = 0.7;
+
+/// Density is given per square of this many map units
+const DENSITY_AREA: f32 = 128.0 * 128.0;
+
+/// The game only accepts reference scales in this range
+const MIN_SCALE: f32 = 0.5;
+const MAX_SCALE: f32 = 2.0;
+
+/// One reference placed by a `func_scatter`
+pub struct Scattered {
+    /// Which triangle and roll placed this, so it keeps its reference number while others come and go
+    pub key: String,
+    pub ref_id: String,
+    /// Game units
+    pub position: SV3,
+    /// Radians, in the same order as `get_rotation` returns them
+    pub rotation: [f32; 3],
+    pub scale: f32,
+}
+
+/// Places the entity's objects across up-facing surfaces. Without `OnWorld` the scatter
+/// brush's own top faces are used; with it, the worldspawn floors inside the brush are.
+/// The same seed always gives the same placements for the same brushes.
+pub fn scatter(
+    map_data: &MapData,
+    entity_id: &EntityId,
+    brushes: &[BrushId],
+    scale_mode: &f32,
+) -> Vec<Scattered> {
+    let prop_map = map_data.get_entity_properties(entity_id);
+
+    let ref_ids: Vec<String> = (1..=8)
+        .map(|index| get_prop(&format!("Object{index}_Id"), &prop_map))
+        .filter(|ref_id| !ref_id.is_empty())
+        .map(|ref_id| ref_id[..min(ref_id.len(), 32)].to_string())
+        .collect();
+
+    if ref_ids.is_empty() {
+        println!("Warning! func_scatter {entity_id} has no objects to place.");
+        return Vec::new();
+    }
+
+    let number = |prop_name: &str, default: f32| {
+        get_prop(prop_name, &prop_map)
+            .parse::<f32>()
+            .unwrap_or(default)
+    };
+
+    let density = number("Density", 1.0).max(0.0);
+    let min_scale = number("MinScale", 1.0).clamp(MIN_SCALE, MAX_SCALE);
+    let max_scale = number("MaxScale", 1.0).clamp(min_scale, MAX_SCALE);
+    let random_yaw = get_prop("RandomYaw", &prop_map) != "0";
+    let on_world = get_prop("OnWorld", &prop_map) == "1";
+    let seed = get_prop("Seed", &prop_map)
+        .parse::<u64>()
+        .unwrap_or_default();
+
+    let triangles: Vec<[SV3; 3]> = match on_world {
+        true => map_data
+            .brush_entities()
+            .into_iter()
+            .filter(|(entity_id, _)| {
+                let classname = get_prop("classname", &map_data.get_entity_properties(entity_id));
+                is_world_class(&classname)
+            })
+            .flat_map(|(_, brushes)| up_facing_triangles(map_data, brushes))
+            .filter(|triangle| overlaps(map_data, brushes, triangle))
+            .collect(),
+        false => up_facing_triangles(map_data, brushes),
+    };
+
+    let mut placements = Vec::new();
+
+    for [a, b, c] in triangles {
+        // Each triangle rolls on its own, so floors elsewhere in the map don't reshuffle this one
+        let hash = triangle_hash(&[a, b, c]);
+        let mut rng = SplitMix64(seed ^ hash);
+
+        let area = (b - a).cross(&(c - a)).norm() / 2.0;
+        let expected = area / DENSITY_AREA * density;
+
+        // The fraction left over decides whether one more object is placed, so small faces still get some
+        let mut count = expected.floor() as usize;
+        if rng.next_f32() < expected.fract() {
+            count += 1;
+        }
+
+        for roll in 0..count {
+            let (mut u, mut v) = (rng.next_f32(), rng.next_f32());
+            if u + v > 1.0 {
+                (u, v) = (1.0 - u, 1.0 - v);
+            }
+            let point = a + (b - a) * u + (c - a) * v;
+
+            // Always rolled, so resizing the volume doesn't reshuffle the objects which stay inside it
+            let ref_id = &ref_ids[rng.next_u64() as usize % ref_ids.len()];
+            let yaw = rng.next_f32() * TAU;
+            let scale = min_scale + (max_scale - min_scale) * rng.next_f32();
+
+            if on_world
+                && !brushes
+                    .iter()
+                    .any(|brush_id| map_data.brush_contains(brush_id, point))
+            {
+                continue;
+            }
+
+            placements.push(Scattered {
+                key: format!("{hash:016x}/{roll}"),
+                ref_id: ref_id.to_string(),
+                position: point * *scale_mode,
+                rotation: [0.0, 0.0, if random_yaw { yaw } else { 0.0 }],
+                scale,
+            });
+        }
+    }
+
+    placements
+}
+
+/// Whether the triangle's bounds touch any of the brushes' bounds
+fn overlaps(map_data: &MapData, brushes: &[BrushId], [a, b, c]: &[SV3; 3]) -> bool {
+    let triangle_mins = a.inf(b).inf(c);
+    let triangle_maxs = a.sup(b).sup(c);
+
+    brushes.iter().any(|brush_id| {
+        let (mins, maxs) = map_data.brush_bounds(&[*brush_id]);
+        (0..3).all(|axis| triangle_mins[axis] <= maxs[axis] && triangle_maxs[axis] >= mins[axis])
+    })
+}
+
+fn triangle_hash(triangle: &[SV3; 3]) -> u64 {
+    let bytes: Vec<u8> = triangle
+        .iter()
+        .flat_map(|vertex| vertex.iter().flat_map(|axis| axis.to_bits().to_le_bytes()))
+        .collect();
+    fnv1a(&bytes)
+}
+
+fn up_facing_triangles(map_data: &MapData, brushes: &[BrushId]) -> Vec<[SV3; 3]> {
+    let mut triangles = Vec::new();
+
+    for brush_id in brushes {
+        if map_data.brush_is_origin(brush_id) {
+            continue;
+        }
+
+        for face_id in map_data
+            .geomap
+            .brush_faces
+            .get(brush_id)
+            .expect("Brush faces should always be valid")
+        {
+            let is_up_facing = map_data
+                .flat_normals
+                .get(face_id)
+                .and_then(|normals| normals.first())
+                .is_some_and(|normal| normal.z >= MIN_SURFACE_NORMAL_Z);
+
+            if !is_up_facing {
+                continue;
+            }
+
+            let vertices = map_data.face_vertices.get(face_id).unwrap();
+            let indices = map_data.face_tri_indices.get(face_id).unwrap();
+
+            for triangle in indices.chunks_exact(3) {
+                triangles.push([
+                    vertices[triangle[0]],
+                    vertices[triangle[1]],
+                    vertices[triangle[2]],
+                ]);
+            }
+        }
+    }
+
+    triangles
+}
+
+/// Small seeded generator, so scatters come out the same on every platform and build
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Between 0 and 1, excluding 1
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
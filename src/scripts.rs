@@ -0,0 +1,352 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use tes3::esp::TES3Object;
+
+use crate::{fgd, game_object, get_prop};
+
+/// Compiles every `.mwscript` file in the `scripts` folder next to the map, as written
+pub fn from_folder(map_name: &str) -> Vec<TES3Object> {
+    let scripts_dir = map_folder(map_name).join("scripts");
+
+    let Ok(entries) = fs::read_dir(&scripts_dir) else {
+        return Vec::new();
+    };
+
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("mwscript"))
+        })
+        .collect();
+    paths.sort();
+
+    paths
+        .iter()
+        .map(|path| {
+            let text = read_source(path);
+            let script_id = declared_id(&text).expect(&format!(
+                "{} has no Begin line, so its script id is unknown!",
+                path.display()
+            ));
+            compile(&script_id, &text)
+        })
+        .collect()
+}
+
+/// Compiles a script file named by an entity. `{{ScriptId}}` and `{{<Property>}}`
+/// placeholders are filled in from the entity, so one file can serve many objects.
+/// Properties the map leaves out take their fgd default.
+pub fn from_template(
+    map_name: &str,
+    script_file: &str,
+    script_id: &str,
+    entity_props: &HashMap<&String, &String>,
+    entity_classes: &[fgd::EntityClass],
+) -> TES3Object {
+    let template = read_source(&map_folder(map_name).join(script_file));
+    let text = render_template(&template, script_id, entity_props, entity_classes);
+
+    let script_id = declared_id(&text).unwrap_or(script_id.to_string());
+    compile(&script_id, &text)
+}
+
+/// Checks the script before building its record. Broken scripts stop the build,
+/// since the game would otherwise only report them once the plugin is loaded.
+pub fn compile(script_id: &str, text: &str) -> TES3Object {
+    if let Err(error) = check_syntax(script_id, text) {
+        panic!("Script {script_id} failed to compile! {error}");
+    }
+
+    game_object::script(script_id, text)
+}
+
+pub fn render_template(
+    template: &str,
+    script_id: &str,
+    entity_props: &HashMap<&String, &String>,
+    entity_classes: &[fgd::EntityClass],
+) -> String {
+    let classname = get_prop("classname", entity_props);
+
+    let mut text = String::with_capacity(template.len());
+    let mut rest = template;
+
+    // One pass from left to right, so values are never searched for further placeholders
+    while let Some(start) = rest.find("{{") {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let value = rest.find("}}").and_then(|end| {
+            let key = &rest[2..end];
+            let value = match key {
+                "ScriptId" => script_id.to_string(),
+                _ => match entity_props.get(&key.to_string()) {
+                    Some(value) => value.to_string(),
+                    None => fgd::property_default(entity_classes, &classname, key)?,
+                },
+            };
+            Some((value, end + 2))
+        });
+
+        let Some((value, length)) = value else {
+            let placeholder: String = rest.chars().take_while(|c| *c != '\n').collect();
+            panic!("Script {script_id} uses {placeholder}, which neither the entity nor its fgd class defines!");
+        };
+
+        text.push_str(&value);
+        rest = &rest[length..];
+    }
+
+    text.push_str(rest);
+    text
+}
+
+/// Structural checks only: the Begin/End pair, block nesting and quoting.
+/// Function names and arguments are left to the game's own compiler.
+pub fn check_syntax(script_id: &str, text: &str) -> Result<(), String> {
+    if script_id.len() > 32 {
+        return Err("Script ids are limited to 32 characters.".to_string());
+    }
+
+    let mut began = false;
+    let mut ended = false;
+    let mut blocks: Vec<(&str, usize)> = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let statement = strip_comment(line)
+            .ok_or(format!("Line {line_number} has an unterminated quote."))?
+            .trim();
+
+        if statement.is_empty() {
+            continue;
+        }
+
+        if ended {
+            return Err(format!("Line {line_number} comes after End."));
+        }
+
+        let keyword = first_word(statement).to_ascii_lowercase();
+
+        if !began {
+            if keyword != "begin" {
+                return Err(format!(
+                    "Line {line_number} should be \"Begin {script_id}\"."
+                ));
+            }
+
+            match statement.split_whitespace().nth(1) {
+                Some(name) if name.eq_ignore_ascii_case(script_id) => {}
+                Some(name) => {
+                    return Err(format!(
+                        "Line {line_number} begins {name}, but the script id is {script_id}."
+                    ))
+                }
+                None => return Err(format!("Line {line_number} has no script name.")),
+            }
+
+            began = true;
+            continue;
+        }
+
+        match keyword.as_str() {
+            "begin" => return Err(format!("Line {line_number} has a second Begin.")),
+            "if" => blocks.push(("if", line_number)),
+            "while" => blocks.push(("while", line_number)),
+            "elseif" | "else" => match blocks.last() {
+                Some(("if", _)) => {}
+                _ => {
+                    return Err(format!(
+                        "Line {line_number} has {keyword} outside of an if."
+                    ))
+                }
+            },
+            "endif" | "endwhile" => {
+                let opener = &keyword[3..];
+                match blocks.pop() {
+                    Some((block, _)) if block == opener => {}
+                    Some((block, opened)) => {
+                        return Err(format!(
+                            "Line {line_number} closes {opener}, but the {block} on line {opened} is still open."
+                        ))
+                    }
+                    None => return Err(format!("Line {line_number} has {keyword} without {opener}.")),
+                }
+            }
+            "end" => {
+                if let Some((block, opened)) = blocks.last() {
+                    return Err(format!("The {block} on line {opened} is never closed."));
+                }
+                ended = true;
+            }
+            _ => {}
+        }
+    }
+
+    match (began, ended) {
+        (false, _) => Err("Script is empty.".to_string()),
+        (true, false) => Err("Script has no End.".to_string()),
+        _ => Ok(()),
+    }
+}
+
+/// Name given on the script's Begin line
+pub fn declared_id(text: &str) -> Option<String> {
+    text.lines()
+        .filter_map(strip_comment)
+        .map(str::trim)
+        .find(|statement| !statement.is_empty())
+        .filter(|statement| first_word(statement).eq_ignore_ascii_case("begin"))
+        .and_then(|statement| statement.split_whitespace().nth(1))
+        .map(str::to_string)
+}
+
+/// Everything before a `;` which isn't inside quotes, or None if a quote is left open
+fn strip_comment(line: &str) -> Option<&str> {
+    let mut quoted = false;
+
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return Some(&line[..index]),
+            _ => {}
+        }
+    }
+
+    match quoted {
+        true => None,
+        false => Some(line),
+    }
+}
+
+/// Keywords can be followed directly by a parenthesis, as in `if(`
+fn first_word(statement: &str) -> &str {
+    let end = statement
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(statement.len());
+    &statement[..end]
+}
+
+fn map_folder(map_name: &str) -> &Path {
+    Path::new(map_name).parent().unwrap_or(Path::new("."))
+}
+
+fn read_source(path: &Path) -> String {
+    fs::read_to_string(path).expect(&format!("Reading script {} failed!", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fgd::{EntityClass, Property};
+
+    fn render(template: &str, props: &[(&str, &str)]) -> String {
+        let props: Vec<(String, String)> = props
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        let prop_map = props.iter().map(|(key, value)| (key, value)).collect();
+        let entity_classes = vec![
+            EntityClass::base("Guarded", "")
+                .properties(vec![Property::integer("Alarm", "").default("30")]),
+            EntityClass::solid("func_guard", "")
+                .bases(&["Guarded"])
+                .properties(vec![Property::string("Target", "")]),
+        ];
+
+        render_template(template, "guard_sc", &prop_map, &entity_classes)
+    }
+
+    #[test]
+    fn placeholders_are_filled_in_from_the_entity() {
+        let text = render(
+            "Begin {{ScriptId}}\nSet {{Target}} to 1\nEnd",
+            &[("classname", "func_guard"), ("Target", "door_01")],
+        );
+        assert_eq!(text, "Begin guard_sc\nSet door_01 to 1\nEnd");
+    }
+
+    #[test]
+    fn values_are_not_searched_for_placeholders() {
+        let text = render(
+            "{{Target}} {{ScriptId}}",
+            &[("classname", "func_guard"), ("Target", "{{ScriptId}}")],
+        );
+        assert_eq!(text, "{{ScriptId}} guard_sc");
+    }
+
+    #[test]
+    fn missing_props_fall_back_to_the_fgd_default() {
+        let text = render("{{Alarm}}", &[("classname", "func_guard")]);
+        assert_eq!(text, "30");
+    }
+
+    #[test]
+    #[should_panic(expected = "{{Target}}")]
+    fn props_without_a_default_panic() {
+        render("{{Target}}", &[("classname", "func_guard")]);
+    }
+
+    #[test]
+    fn valid_script_passes() {
+        let text = "Begin guard_sc ; guards\n\
+                    short done\n\
+                    if ( done == 0 )\n\
+                    \tMessageBox \"Halt; who goes there?\"\n\
+                    elseif ( done == 1 )\n\
+                    \twhile ( done < 3 )\n\
+                    \t\tset done to done + 1\n\
+                    \tendwhile\n\
+                    endif\n\
+                    End guard_sc\n";
+        assert_eq!(check_syntax("guard_sc", text), Ok(()));
+    }
+
+    #[test]
+    fn malformed_scripts_are_rejected() {
+        let cases = [
+            ("", "Script is empty."),
+            ("Begin guard_sc\n", "Script has no End."),
+            (
+                "Begin other_sc\nEnd\n",
+                "Line 1 begins other_sc, but the script id is guard_sc.",
+            ),
+            (
+                "Begin guard_sc\nif ( 1 )\nEnd\n",
+                "The if on line 2 is never closed.",
+            ),
+            (
+                "Begin guard_sc\nendif\nEnd\n",
+                "Line 2 has endif without if.",
+            ),
+            (
+                "Begin guard_sc\nelse\nEnd\n",
+                "Line 2 has else outside of an if.",
+            ),
+            (
+                "Begin guard_sc\nMessageBox \"Halt\nEnd\n",
+                "Line 2 has an unterminated quote.",
+            ),
+            (
+                "Begin guard_sc\nEnd\nshort late\n",
+                "Line 3 comes after End.",
+            ),
+        ];
+
+        for (text, error) in cases {
+            assert_eq!(
+                check_syntax("guard_sc", text),
+                Err(error.to_string()),
+                "{text:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn long_script_ids_are_rejected() {
+        let script_id = "a".repeat(33);
+        let text = format!("Begin {script_id}\nEnd\n");
+        assert!(check_syntax(&script_id, &text).is_err());
+    }
+}
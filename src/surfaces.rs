@@ -3,6 +3,16 @@ pub const BOOK_START_DEFAULT: &str =
 
 pub mod colors {}
 
+/// Faces with these in their texture name are liquid, and have no collision
+pub const LIQUID_TEXTURES: &[&str] = &["slime", "water", "lava", "mwat"];
+
+pub fn is_liquid(texture_name: &str) -> bool {
+    let texture_name = texture_name.to_ascii_lowercase();
+    LIQUID_TEXTURES
+        .iter()
+        .any(|liquid| texture_name.contains(liquid))
+}
+
 pub enum NiBroomSurface {
     NoClip = 1,
     SmoothShading = 2,
@@ -0,0 +1,147 @@
+use shambler::Vector3 as SV3;
+use std::collections::HashMap;
+
+use crate::{get_prop, manifest::fnv1a};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TriggerKind {
+    Script,
+    Hurt,
+}
+
+/// Brush entities which only exist as a volume, checked against the player's position by script.
+/// Bounds are in game units, since the script compares them against GetPos.
+#[derive(Debug)]
+pub struct Trigger {
+    pub kind: TriggerKind,
+    pub mins: SV3,
+    pub maxs: SV3,
+}
+
+impl Trigger {
+    pub fn from_props(classname: &str, bounds: (SV3, SV3), scale_mode: &f32) -> Option<Trigger> {
+        let kind = match classname {
+            "trigger_Script" => TriggerKind::Script,
+            "trigger_Hurt" => TriggerKind::Hurt,
+            _ => return None,
+        };
+
+        let (mins, maxs) = bounds;
+
+        Some(Trigger {
+            kind,
+            mins: mins * *scale_mode,
+            maxs: maxs * *scale_mode,
+        })
+    }
+
+    /// The script bakes in the bounds and props, so its id carries a hash of them.
+    /// Triggers only share a script when they would compile to the same one.
+    pub fn script_id(&self, ref_id: &str, entity_props: &HashMap<&String, &String>) -> String {
+        let hash = fnv1a(self.script("", entity_props).as_bytes()) as u32;
+        format!("{}_{hash:08x}", &ref_id[..ref_id.len().min(23)])
+    }
+
+    /// MWScript has no logical operators, so each bound gets its own nested check
+    pub fn script(&self, script_id: &str, entity_props: &HashMap<&String, &String>) -> String {
+        let mut script = format!(
+            "Begin {script_id}\r\n\
+             \r\n\
+             short isInside\r\n\
+             short wasInside\r\n\
+             short done\r\n\
+             float playerPos\r\n\
+             float damage\r\n\
+             \r\n\
+             set isInside to 0\r\n"
+        );
+
+        let checks = [("X", 0), ("Y", 1), ("Z", 2)];
+
+        for (depth, (axis, index)) in checks.iter().enumerate() {
+            let indent = "\t".repeat(depth);
+            script.push_str(&format!(
+                "{indent}set playerPos to ( player->GetPos {axis} )\r\n\
+                 {indent}if ( playerPos >= {} )\r\n\
+                 {indent}if ( playerPos <= {} )\r\n",
+                self.mins[*index], self.maxs[*index]
+            ));
+        }
+
+        script.push_str(&format!(
+            "{}set isInside to 1\r\n",
+            "\t".repeat(checks.len())
+        ));
+
+        for depth in (0..checks.len()).rev() {
+            let indent = "\t".repeat(depth);
+            script.push_str(&format!("{indent}endif\r\n{indent}endif\r\n"));
+        }
+
+        script.push_str("\r\n");
+        script.push_str(&match self.kind {
+            TriggerKind::Script => enter_block(entity_props),
+            TriggerKind::Hurt => hurt_block(entity_props),
+        });
+        script.push_str(&format!("\r\nEnd {script_id}\r\n"));
+
+        script
+    }
+}
+
+/// Runs once each time the player steps in, or only the first time if Once is set
+fn enter_block(entity_props: &HashMap<&String, &String>) -> String {
+    let mut statements = String::new();
+
+    let global = get_prop("Global", entity_props);
+    if !global.is_empty() {
+        let value = match get_prop("GlobalValue", entity_props) {
+            value if value.is_empty() => "1".to_string(),
+            value => value,
+        };
+        statements.push_str(&format!("\t\tset {global} to {value}\r\n"));
+    }
+
+    // Map values can't hold line breaks, so snippets separate statements with \n
+    for statement in get_prop("OnEnter", entity_props).split("\\n") {
+        let statement = statement.trim();
+        if !statement.is_empty() {
+            statements.push_str(&format!("\t\t{statement}\r\n"));
+        }
+    }
+
+    if get_prop("Once", entity_props) == "1" {
+        statements.push_str("\t\tset done to 1\r\n");
+    }
+
+    format!(
+        "if ( done == 1 )\r\n\
+         \treturn\r\n\
+         endif\r\n\
+         \r\n\
+         if ( isInside == 1 )\r\n\
+         \tif ( wasInside == 0 )\r\n\
+         \t\tset wasInside to 1\r\n\
+         {statements}\
+         \tendif\r\n\
+         else\r\n\
+         \tset wasInside to 0\r\n\
+         endif\r\n"
+    )
+}
+
+/// Damage is given per second, so it is spread over each frame the player stays inside
+fn hurt_block(entity_props: &HashMap<&String, &String>) -> String {
+    let damage = match get_prop("Damage", entity_props).parse::<f32>() {
+        Ok(damage) => damage,
+        Err(_) => 10.0,
+    };
+
+    format!(
+        "if ( isInside == 1 )\r\n\
+         \tset damage to ( GetSecondsPassed * {damage} )\r\n\
+         \tset damage to ( 0 - damage )\r\n\
+         \tplayer->ModCurrentHealth damage\r\n\
+         endif\r\n"
+    )
+}